@@ -0,0 +1,92 @@
+//! Times index-heavy work with the default SipHash hasher against a fast,
+//! non-keyed one passed to `open_with_hasher`.
+//!
+//! Run with `cargo run --release --example hasher_bench [keys]`.
+
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::time::{Duration, Instant};
+
+use rusk::RuskStore;
+
+/// 64-bit FNV-1a. Fast on short keys, but anyone choosing the keys can
+/// force collisions.
+struct FnvHasher {
+    hash: u64,
+}
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher {
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+fn key(i: usize) -> String {
+    format!("user:{i:08}:session")
+}
+
+/// Reopens the store at `path`, replaying the log into a fresh index, then
+/// looks every key up ten times.
+fn run<S: BuildHasher + Clone>(
+    path: &std::path::Path,
+    keys: &[String],
+    hasher: S,
+) -> (Duration, Duration) {
+    let start = Instant::now();
+    let store = RuskStore::builder(path)
+        .open_with_hasher(hasher)
+        .expect("failed to open store");
+    let open = start.elapsed();
+
+    let rounds = 10;
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for key in keys {
+            std::hint::black_box(store.get_status(key));
+        }
+    }
+    let lookups = start.elapsed();
+
+    (open, lookups)
+}
+
+fn main() {
+    let keys = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("key count must be a number"))
+        .unwrap_or(200_000);
+    let keys: Vec<String> = (0..keys).map(key).collect();
+
+    let dir = tempfile::tempdir().expect("failed to create a temporary directory");
+    let path = dir.path().join("bench.log");
+    {
+        let mut store = RuskStore::open(&path).expect("failed to create store");
+        for key in &keys {
+            store
+                .set(key.clone(), "x".to_owned())
+                .expect("failed to write");
+        }
+    }
+
+    let (sip_open, sip_lookups) = run(&path, &keys, std::hash::RandomState::new());
+    let (fnv_open, fnv_lookups) = run(&path, &keys, BuildHasherDefault::<FnvHasher>::default());
+
+    println!("{} keys", keys.len());
+    println!("{:<8} {:>12} {:>12}", "hasher", "open", "lookups");
+    println!("{:<8} {:>12?} {:>12?}", "siphash", sip_open, sip_lookups);
+    println!("{:<8} {:>12?} {:>12?}", "fnv", fnv_open, fnv_lookups);
+}
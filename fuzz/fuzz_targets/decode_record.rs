@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusk::format;
+
+// Decoding arbitrary bytes must fail cleanly: no panics, and no allocations
+// sized by an untrusted length prefix.
+fuzz_target!(|data: &[u8]| {
+    let _ = format::decode_header(data);
+    let _ = format::is_torn_header(data);
+
+    for version in [
+        format::LEGACY_VERSION,
+        1,
+        format::CURRENT_VERSION,
+        format::BINARY_VERSION,
+        format::PLAINTEXT_VERSION,
+    ] {
+        let mut bytes = data;
+        while let Ok((_, length)) = format::decode_record(bytes, version) {
+            bytes = &bytes[length..];
+        }
+    }
+});
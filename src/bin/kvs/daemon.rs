@@ -0,0 +1,210 @@
+//! `kvs daemon`: keeps a store open and serves other `kvs` invocations over
+//! a Unix domain socket, so they don't each replay the log.
+//!
+//! Each connection carries one request and one response, each a line of
+//! JSON.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use rusk::{KeyValueStore, Result, RuskError, RuskStore};
+use serde::{Deserialize, Serialize};
+
+const SOCKET_NAME: &str = "kvs.sock";
+
+/// How long the daemon waits for a connected client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+enum Request {
+    Set { key: String, value: String },
+    Get { key: String },
+    Rm { key: String },
+    Compact,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    Done,
+    Value(Option<String>),
+    KeyNotFound(String),
+    Error(String),
+}
+
+fn socket_path(store_dir: &Path) -> PathBuf {
+    store_dir.join(SOCKET_NAME)
+}
+
+/// Returns `true` if a daemon is accepting connections for `store_dir`.
+pub fn is_running(store_dir: &Path) -> bool {
+    UnixStream::connect(socket_path(store_dir)).is_ok()
+}
+
+/// Serves `store` until the process receives SIGTERM or SIGINT, then closes
+/// it, flushing any buffered writes.
+pub fn serve(mut store: RuskStore, store_dir: &Path) -> Result<()> {
+    let path = socket_path(store_dir);
+    if is_running(store_dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "a daemon is already serving this store",
+        )
+        .into());
+    }
+    // Nothing is listening, so any socket file is left over from a daemon
+    // that didn't shut down cleanly.
+    let _ = fs::remove_file(&path);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let signals = block_shutdown_signals();
+    let listener = UnixListener::bind(&path)?;
+    {
+        let stop = Arc::clone(&stop);
+        let path = path.clone();
+        thread::spawn(move || {
+            wait_for(&signals);
+            stop.store(true, Ordering::SeqCst);
+            // Wake the accept loop so it notices.
+            let _ = UnixStream::connect(&path);
+        });
+    }
+
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let result = stream
+            .map_err(RuskError::from)
+            .and_then(|stream| handle_connection(&mut store, stream));
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    }
+
+    drop(listener);
+    let _ = fs::remove_file(&path);
+    store.close()
+}
+
+/// Blocks SIGTERM and SIGINT in this thread and any it spawns, so they can
+/// be waited for with [`wait_for`] instead of killing the process.
+fn block_shutdown_signals() -> libc::sigset_t {
+    // SAFETY: `set` is initialised by `sigemptyset` before use, and the
+    // calls only read and write it.
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+        set
+    }
+}
+
+/// Waits until one of the blocked signals in `set` is delivered.
+fn wait_for(set: &libc::sigset_t) {
+    let mut signal = 0;
+    // SAFETY: `set` is a valid signal set and `signal` a valid out pointer.
+    unsafe {
+        libc::sigwait(set, &mut signal);
+    }
+}
+
+fn handle_connection(store: &mut RuskStore, stream: UnixStream) -> Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut line = String::new();
+    // Connections that close without a request are liveness checks.
+    if BufReader::new(&stream).read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => apply(store, request),
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+    write_message(&stream, &response)
+}
+
+fn apply(store: &mut RuskStore, request: Request) -> Response {
+    let result = match request {
+        Request::Set { key, value } => store.set(key, value).map(|()| Response::Done),
+        Request::Get { key } => store.get(key).map(Response::Value),
+        Request::Rm { key } => store.remove(key).map(|()| Response::Done),
+        Request::Compact => store.compact().map(|_| Response::Done),
+    };
+    match result {
+        Ok(response) => response,
+        Err(RuskError::KeyNotFound(key)) => Response::KeyNotFound(key),
+        Err(e) => Response::Error(e.to_string()),
+    }
+}
+
+fn write_message(mut stream: &UnixStream, message: &impl Serialize) -> Result<()> {
+    let mut bytes = serde_json::to_vec(message)?;
+    bytes.push(b'\n');
+    stream.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Sends commands to the daemon serving a store directory.
+pub struct Client {
+    path: PathBuf,
+}
+
+impl Client {
+    pub fn new(store_dir: &Path) -> Self {
+        Client {
+            path: socket_path(store_dir),
+        }
+    }
+
+    fn call(&self, request: Request) -> Result<Response> {
+        let stream = UnixStream::connect(&self.path)?;
+        write_message(&stream, &request)?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        match serde_json::from_str(&line)? {
+            Response::KeyNotFound(key) => Err(RuskError::KeyNotFound(key)),
+            Response::Error(message) => Err(io::Error::other(message).into()),
+            response => Ok(response),
+        }
+    }
+
+    fn expect_done(&self, request: Request) -> Result<()> {
+        match self.call(request)? {
+            Response::Done => Ok(()),
+            _ => Err(RuskError::UnexpectedCommand),
+        }
+    }
+}
+
+impl KeyValueStore for Client {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.expect_done(Request::Set { key, value })
+    }
+
+    fn get(&mut self, key: String) -> Result<Option<String>> {
+        match self.call(Request::Get { key })? {
+            Response::Value(value) => Ok(value),
+            _ => Err(RuskError::UnexpectedCommand),
+        }
+    }
+
+    fn remove(&mut self, key: String) -> Result<()> {
+        self.expect_done(Request::Rm { key })
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        self.expect_done(Request::Compact)
+    }
+}
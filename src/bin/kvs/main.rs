@@ -0,0 +1,430 @@
+use std::env;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use rusk::{KeyValueStore, Result, RuskError, RuskStore};
+use serde::{Deserialize, Serialize};
+
+#[cfg(unix)]
+mod daemon;
+
+/// The key passed to `get` or `rm` doesn't exist.
+const EXIT_KEY_NOT_FOUND: u8 = 1;
+/// The store couldn't be opened or the operation failed.
+const EXIT_ERROR: u8 = 3;
+
+#[derive(Parser)]
+#[command(name = "rusk")]
+#[command(about = "A Bitcask-style key-value store", long_about = None)]
+#[command(version)]
+#[command(after_help = "Exit codes:
+  0  Success
+  1  Key not found (get, rm)
+  2  Invalid usage
+  3  Any other error, e.g. the store couldn't be opened")]
+struct Cli {
+    /// Store directory (defaults to the current directory)
+    #[arg(long, global = true, env = "RUSK_PATH")]
+    path: Option<PathBuf>,
+    /// Create the store directory if it doesn't exist
+    #[arg(long, global = true)]
+    create: bool,
+    /// Cache the index between invocations to skip replaying the log
+    #[arg(long, global = true)]
+    index_cache: bool,
+    /// Only print requested values: no status or missing-key messages
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Set a key-value pair
+    Set { key: String, value: String },
+    /// Get the value for a key
+    Get { key: String },
+    /// Remove a key
+    Rm { key: String },
+    /// Manually trigger compaction
+    Compact {
+        /// Only print how much compaction would reclaim
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print every live key/value pair, sorted by key
+    Dump {
+        #[arg(long, value_enum, default_value_t = Format::Tsv)]
+        format: Format,
+    },
+    /// Import key/value pairs from stdin, in the format written by `dump`
+    Load {
+        #[arg(long, value_enum, default_value_t = Format::Tsv)]
+        format: Format,
+    },
+    /// Open the store once and run commands read from stdin until EOF
+    Repl,
+    /// Keep the store open and serve `set`, `get`, `rm` and `compact` from
+    /// other invocations over a Unix socket (`kvs.sock` in the store
+    /// directory) until terminated
+    Daemon,
+}
+
+/// Line format for `dump` and `load`.
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// One JSON object per line: `{"key":...,"value":...}`
+    Json,
+    /// One `key<TAB>value` line per pair; backslash, tab, newline and
+    /// carriage return are escaped as `\\`, `\t`, `\n` and `\r`
+    Tsv,
+}
+
+/// A key/value pair as written by `dump --format json`.
+#[derive(Serialize, Deserialize)]
+struct Pair {
+    key: String,
+    value: String,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    let current_dir = env::current_dir()?;
+    let store_dir = match cli.path {
+        Some(path) => current_dir.join(path),
+        None => current_dir,
+    };
+
+    if !cli.create && !store_dir.is_dir() {
+        eprintln!(
+            "Store directory does not exist: {} (use --create to create it)",
+            store_dir.display()
+        );
+        return Ok(ExitCode::from(EXIT_ERROR));
+    }
+
+    #[cfg(unix)]
+    if daemon::is_running(&store_dir) {
+        return match cli.command {
+            command @ (Commands::Set { .. }
+            | Commands::Get { .. }
+            | Commands::Rm { .. }
+            | Commands::Compact { dry_run: false }) => {
+                basic_command(&mut daemon::Client::new(&store_dir), command, cli.quiet)
+            }
+            _ => {
+                eprintln!(
+                    "A daemon is serving {}; only set, get, rm and compact can be used until it stops",
+                    store_dir.display()
+                );
+                Ok(ExitCode::from(EXIT_ERROR))
+            }
+        };
+    }
+
+    let mut store = RuskStore::builder(&store_dir)
+        .index_cache(cli.index_cache)
+        .open()?;
+
+    match cli.command {
+        Commands::Dump { format } => {
+            dump(&mut store, format)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Load { format } => load(&mut store, format, cli.quiet),
+        Commands::Compact { dry_run: true } => {
+            let estimate = store.compaction_estimate();
+            println!("Current size: {} bytes", estimate.current_size);
+            println!("Estimated size: {} bytes", estimate.estimated_size);
+            println!("Reclaimable: {} bytes", estimate.reclaimable);
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Repl => repl(store, cli.quiet),
+        #[cfg(unix)]
+        Commands::Daemon => {
+            daemon::serve(store, &store_dir)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        #[cfg(not(unix))]
+        Commands::Daemon => {
+            eprintln!("The daemon is only supported on Unix");
+            Ok(ExitCode::from(EXIT_ERROR))
+        }
+        command => basic_command(&mut store, command, cli.quiet),
+    }
+}
+
+/// Runs one of the commands that only need the basic key-value operations,
+/// either on the store directly or through a daemon.
+fn basic_command(
+    store: &mut impl KeyValueStore,
+    command: Commands,
+    quiet: bool,
+) -> Result<ExitCode> {
+    let found = match command {
+        Commands::Set { key, value } => {
+            store.set(key, value)?;
+            true
+        }
+        Commands::Get { key } => match store.get(key)? {
+            Some(value) => {
+                println!("{}", value);
+                true
+            }
+            None => false,
+        },
+        Commands::Rm { key } => match store.remove(key) {
+            Ok(()) => true,
+            Err(RuskError::KeyNotFound(_)) => false,
+            Err(e) => return Err(e),
+        },
+        Commands::Compact { .. } => {
+            store.compact()?;
+            if !quiet {
+                println!("Compaction complete");
+            }
+            true
+        }
+        _ => unreachable!("not a basic command"),
+    };
+
+    if found {
+        return Ok(ExitCode::SUCCESS);
+    }
+    if !quiet {
+        eprintln!("Key not found");
+    }
+    Ok(ExitCode::from(EXIT_KEY_NOT_FOUND))
+}
+
+fn dump(store: &mut RuskStore, format: Format) -> Result<()> {
+    let mut keys: Vec<String> = store.keys().map(str::to_owned).collect();
+    keys.sort_unstable();
+    let mut values = store.get_map(&keys)?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for key in keys {
+        let Some(value) = values.remove(&key) else {
+            continue;
+        };
+        match format {
+            Format::Json => {
+                serde_json::to_writer(&mut out, &Pair { key, value })?;
+                writeln!(out)?;
+            }
+            Format::Tsv => writeln!(out, "{}\t{}", escape_tsv(&key), escape_tsv(&value))?,
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Escapes the characters that would otherwise break a TSV line.
+fn escape_tsv(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Imports pairs from stdin with a single batched write.
+///
+/// Reading stops at the first malformed line; the pairs before it are still
+/// imported, and the line number and import count are reported.
+fn load(store: &mut RuskStore, format: Format, quiet: bool) -> Result<ExitCode> {
+    let mut pairs = Vec::new();
+    let mut malformed = None;
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let pair = match format {
+            Format::Json => serde_json::from_str(&line).map_err(|e| e.to_string()),
+            Format::Tsv => parse_tsv(&line),
+        };
+        match pair {
+            Ok(Pair { key, value }) => pairs.push((key, value)),
+            Err(message) => {
+                malformed = Some((index + 1, message));
+                break;
+            }
+        }
+    }
+
+    let imported = pairs.len();
+    store.set_batch(pairs)?;
+
+    match malformed {
+        Some((line, message)) => {
+            eprintln!("Malformed input on line {}: {}", line, message);
+            eprintln!("Imported {} pairs before the error", imported);
+            Ok(ExitCode::from(EXIT_ERROR))
+        }
+        None => {
+            if !quiet {
+                println!("Imported {} pairs", imported);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Parses a `key<TAB>value` line, undoing [`escape_tsv`].
+fn parse_tsv(line: &str) -> std::result::Result<Pair, String> {
+    let Some((key, value)) = line.split_once('\t') else {
+        return Err("expected a tab between key and value".to_owned());
+    };
+    if value.contains('\t') {
+        return Err("unescaped tab in value".to_owned());
+    }
+    Ok(Pair {
+        key: unescape_tsv(key)?,
+        value: unescape_tsv(value)?,
+    })
+}
+
+fn unescape_tsv(field: &str) -> std::result::Result<String, String> {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => return Err(format!("unknown escape sequence \\{}", other)),
+            None => return Err("trailing backslash".to_owned()),
+        }
+    }
+    Ok(unescaped)
+}
+
+const REPL_HELP: &str =
+    "Commands: set <key> <value>, get <key>, rm <key>, keys, compact, stats, exit";
+
+/// Runs commands from stdin against a store opened once, so the log is only
+/// replayed at startup. Errors are reported and the loop carries on; EOF or
+/// `exit` closes the store.
+fn repl(mut store: RuskStore, quiet: bool) -> Result<ExitCode> {
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        if interactive {
+            print!("rusk> ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            if interactive {
+                println!();
+            }
+            break;
+        };
+        let line = match line {
+            Ok(line) => line,
+            // The offending line has been consumed, so carry on with the next.
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("Invalid input: {}", e);
+                continue;
+            }
+            // Anything else would most likely fail again on every read, so
+            // stop as if stdin had ended, still closing the store cleanly.
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                break;
+            }
+        };
+        let line = line.trim();
+        if line == "exit" || line == "quit" {
+            break;
+        }
+        if let Err(e) = repl_command(&mut store, line, quiet) {
+            eprintln!("{}", e);
+        }
+    }
+
+    store.close()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn repl_command(store: &mut RuskStore, line: &str, quiet: bool) -> Result<()> {
+    let (command, args) = match line.split_once(char::is_whitespace) {
+        Some((command, args)) => (command, args.trim_start()),
+        None => (line, ""),
+    };
+
+    match (command, args) {
+        ("", _) => {}
+        ("set", args) => match args.split_once(char::is_whitespace) {
+            Some((key, value)) => {
+                store.set(key.to_owned(), value.trim_start().to_owned())?;
+                if !quiet {
+                    println!("OK");
+                }
+            }
+            None => eprintln!("Usage: set <key> <value>"),
+        },
+        ("get", key) if !key.is_empty() => match store.get(key.to_owned())? {
+            Some(value) => println!("{}", value),
+            None if !quiet => eprintln!("Key not found"),
+            None => {}
+        },
+        ("rm", key) if !key.is_empty() => match store.remove(key.to_owned()) {
+            Ok(()) if !quiet => println!("OK"),
+            Ok(()) => {}
+            Err(RuskError::KeyNotFound(_)) if !quiet => eprintln!("Key not found"),
+            Err(RuskError::KeyNotFound(_)) => {}
+            Err(e) => return Err(e),
+        },
+        ("keys", "") => {
+            let mut keys: Vec<&str> = store.keys().collect();
+            keys.sort_unstable();
+            for key in keys {
+                println!("{}", key);
+            }
+        }
+        ("compact", "") => {
+            let report = store.compact()?;
+            if !quiet {
+                println!(
+                    "Compaction complete: {} bytes reclaimed",
+                    report.bytes_reclaimed()
+                );
+            }
+        }
+        ("stats", "") => {
+            println!("keys: {}", store.len());
+            println!("live bytes: {}", store.total_value_bytes());
+            println!("generation: {}", store.generation());
+        }
+        _ => eprintln!("{}", REPL_HELP),
+    }
+    Ok(())
+}
@@ -0,0 +1,478 @@
+use std::hash::BuildHasher;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{LOG_FILE_NAME, RuskStore};
+use crate::error::{Result, RuskError};
+use crate::format;
+use crate::handle::StoreHandle;
+
+/// Controls when buffered writes are flushed to the log file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Flush after every write operation.
+    #[default]
+    EveryWrite,
+    /// Only flush when [`RuskStore::flush`] is called, the write buffer
+    /// fills up, a read needs the buffered data, or the store is dropped.
+    Manual,
+    /// Like [`Manual`](Self::Manual), but also flush automatically once
+    /// enough writes are buffered or enough time has passed.
+    Batched(WriteBatchPolicy),
+}
+
+/// Thresholds for [`FlushMode::Batched`]; whichever is reached first
+/// triggers a flush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBatchPolicy {
+    /// Flush once this many records are buffered.
+    pub max_writes: usize,
+    /// Flush buffered records from a background thread at least this often.
+    pub max_delay: Duration,
+}
+
+/// Controls whether writes are forced to stable storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DurabilityMode {
+    /// Leave it to the OS to write flushed data to disk. Fastest, but a crash
+    /// of the machine may lose recent writes.
+    #[default]
+    None,
+    /// `fsync` the log on every flush, and the compacted log before it
+    /// replaces the live one.
+    Fsync,
+}
+
+/// What happens when a write to the mirror log fails. See
+/// [`RuskStoreBuilder::mirror_path`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MirrorPolicy {
+    /// Fail the write with `RuskError::MirrorWriteFailed`; nothing is
+    /// written to either log.
+    #[default]
+    Fatal,
+    /// Log a warning and stop mirroring until the store is next opened,
+    /// which brings the mirror up to date again.
+    BestEffort,
+}
+
+/// What [`RuskStore::increment`] does when a key's value isn't an integer.
+/// See [`RuskStoreBuilder::invalid_counter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidCounterPolicy {
+    /// Fail with `RuskError::NotACounter`, leaving the value unchanged.
+    #[default]
+    Error,
+    /// Treat the value as `0` and overwrite it.
+    Reset,
+}
+
+/// What [`RuskStore::import_ndjson`] does with a line that isn't a valid
+/// entry. See [`RuskStoreBuilder::import_errors`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportErrorPolicy {
+    /// Skip the line and record its number in the report.
+    #[default]
+    Skip,
+    /// Stop the import with `RuskError::InvalidImportLine`.
+    Abort,
+}
+
+/// Default for [`RuskStoreBuilder::compaction_threshold`]: 1 MiB.
+pub(crate) const DEFAULT_COMPACTION_THRESHOLD: u64 = 1024 * 1024;
+
+/// Default size of the log's read and write buffers.
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// How records are encoded in the log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// JSON-serialized commands.
+    #[default]
+    Json,
+    /// A compact binary encoding that stores keys and values as raw,
+    /// length-prefixed bytes. Smaller than JSON for small keys and values.
+    Binary,
+    /// One `key<TAB>value` line per set and one `-key` line per removal,
+    /// readable with ordinary text tools.
+    ///
+    /// Records carry no checksums, sequence numbers, metadata or creation
+    /// times. Writes whose key or value contains a tab or newline, and
+    /// writes with metadata or a creation time, fail with
+    /// `RuskError::PlaintextUnsupported`.
+    Plaintext,
+}
+
+impl Encoding {
+    /// Returns the log format version that uses this encoding.
+    pub(crate) fn version(self) -> u32 {
+        match self {
+            Encoding::Json => format::CURRENT_VERSION,
+            Encoding::Binary => format::BINARY_VERSION,
+            Encoding::Plaintext => format::PLAINTEXT_VERSION,
+        }
+    }
+}
+
+/// Options that control how a [`RuskStore`] behaves once opened.
+#[derive(Debug, Clone)]
+pub(crate) struct Options {
+    pub(crate) key_transform: Option<fn(&str) -> String>,
+    pub(crate) flush_mode: FlushMode,
+    pub(crate) flush_interval: Option<Duration>,
+    pub(crate) durability: DurabilityMode,
+    pub(crate) encoding: Encoding,
+    pub(crate) max_log_size: Option<u64>,
+    pub(crate) max_key_len: Option<usize>,
+    pub(crate) compaction_threshold: u64,
+    pub(crate) log_file_name: String,
+    pub(crate) write_buffer_size: usize,
+    pub(crate) read_buffer_size: usize,
+    pub(crate) track_removed: Option<usize>,
+    pub(crate) versions_retained: usize,
+    pub(crate) record_created_at: bool,
+    pub(crate) verify_on_read: bool,
+    pub(crate) index_cache: bool,
+    pub(crate) mirror_path: Option<PathBuf>,
+    pub(crate) mirror_policy: MirrorPolicy,
+    pub(crate) invalid_counter: InvalidCounterPolicy,
+    pub(crate) strict_reads: bool,
+    pub(crate) import_errors: ImportErrorPolicy,
+    pub(crate) io_retries: usize,
+    pub(crate) value_cache: Option<usize>,
+    pub(crate) auto_compact_interval: Option<Duration>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            key_transform: None,
+            flush_mode: FlushMode::default(),
+            flush_interval: None,
+            durability: DurabilityMode::default(),
+            encoding: Encoding::default(),
+            max_log_size: None,
+            max_key_len: None,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            log_file_name: LOG_FILE_NAME.to_owned(),
+            write_buffer_size: DEFAULT_BUFFER_SIZE,
+            read_buffer_size: DEFAULT_BUFFER_SIZE,
+            track_removed: None,
+            versions_retained: 1,
+            record_created_at: false,
+            verify_on_read: true,
+            index_cache: false,
+            mirror_path: None,
+            mirror_policy: MirrorPolicy::default(),
+            invalid_counter: InvalidCounterPolicy::default(),
+            strict_reads: true,
+            import_errors: ImportErrorPolicy::default(),
+            io_retries: 0,
+            value_cache: None,
+            auto_compact_interval: None,
+        }
+    }
+}
+
+/// Builder for opening a [`RuskStore`] with non-default options.
+#[derive(Debug)]
+pub struct RuskStoreBuilder {
+    path: PathBuf,
+    options: Options,
+}
+
+impl RuskStoreBuilder {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        RuskStoreBuilder {
+            path: path.into(),
+            options: Options::default(),
+        }
+    }
+
+    /// Sets a transform applied to every key before it is indexed or looked
+    /// up, e.g. `str::to_lowercase` for case-insensitive keys.
+    ///
+    /// The transform is applied uniformly by `set`, `get` and `remove`, and
+    /// keys are stored in their transformed form. Changing the transform on
+    /// an existing store is unsupported: keys already in the log keep the
+    /// form they were written with.
+    pub fn key_transform(mut self, transform: fn(&str) -> String) -> Self {
+        self.options.key_transform = Some(transform);
+        self
+    }
+
+    /// Sets when buffered writes are flushed. Defaults to
+    /// [`FlushMode::EveryWrite`].
+    pub fn flush_mode(mut self, mode: FlushMode) -> Self {
+        self.options.flush_mode = mode;
+        self
+    }
+
+    /// In [`FlushMode::Manual`], flushes buffered writes from a background
+    /// thread every `interval`, bounding how long a write can sit unflushed.
+    ///
+    /// Ignored in [`FlushMode::EveryWrite`], where no thread is spawned, and
+    /// in [`FlushMode::Batched`], which uses its own
+    /// [`max_delay`](WriteBatchPolicy::max_delay).
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.options.flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets whether flushed writes and compacted logs are synced to disk.
+    /// Defaults to [`DurabilityMode::None`].
+    pub fn durability(mut self, mode: DurabilityMode) -> Self {
+        self.options.durability = mode;
+        self
+    }
+
+    /// Sets how records are encoded. Defaults to [`Encoding::Json`].
+    ///
+    /// The encoding is recorded in the log's header, so it applies to new
+    /// logs; an existing log keeps its encoding until the next compaction
+    /// rewrites it in this one. Logs in any encoding can always be read.
+    /// Compacting into [`Encoding::Plaintext`] fails, leaving the log as it
+    /// was, if any live value can't be stored as plaintext.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.options.encoding = encoding;
+        self
+    }
+
+    /// Caps the size of the log file at `max` bytes.
+    ///
+    /// A write that would grow the log past the limit first compacts it; if
+    /// the log is still too large, the write fails with
+    /// `RuskError::LogFull` and nothing is written. Reads keep working when
+    /// the log is full. Unlimited by default.
+    pub fn max_log_size(mut self, max: u64) -> Self {
+        self.options.max_log_size = Some(max);
+        self
+    }
+
+    /// Rejects writes of keys longer than `max` bytes with
+    /// `RuskError::KeyTooLong`. Unlimited by default.
+    ///
+    /// The limit applies to the key as stored, i.e. after any
+    /// [`key_transform`](Self::key_transform), and is checked by every
+    /// method that writes a key before anything is written. A batch with
+    /// one key over the limit writes none of its entries. Keys already in
+    /// the log are unaffected.
+    pub fn max_key_len(mut self, max: usize) -> Self {
+        self.options.max_key_len = Some(max);
+        self
+    }
+
+    /// Compacts the log automatically once overwritten and removed records
+    /// take up more than `bytes`. Defaults to 1 MiB.
+    pub fn compaction_threshold(mut self, bytes: u64) -> Self {
+        self.options.compaction_threshold = bytes;
+        self
+    }
+
+    /// Sets the name of the log file within the store directory. Defaults to
+    /// `data.log`.
+    ///
+    /// The hint and compaction files are named after the log, so several
+    /// stores with different log names can share a directory.
+    pub fn log_file_name(mut self, name: impl Into<String>) -> Self {
+        self.options.log_file_name = name.into();
+        self
+    }
+
+    /// Sets the capacity of the buffer writes go through before reaching the
+    /// log. Defaults to 8 KiB.
+    pub fn write_buffer_size(mut self, bytes: usize) -> Self {
+        self.options.write_buffer_size = bytes;
+        self
+    }
+
+    /// Sets the capacity of the buffers used to read the log. Defaults to
+    /// 8 KiB.
+    pub fn read_buffer_size(mut self, bytes: usize) -> Self {
+        self.options.read_buffer_size = bytes;
+        self
+    }
+
+    /// Remembers up to `capacity` recently removed keys so
+    /// [`RuskStore::get_status`] can tell them apart from keys that were never
+    /// set. Disabled by default.
+    pub fn track_removed(mut self, capacity: usize) -> Self {
+        self.options.track_removed = Some(capacity);
+        self
+    }
+
+    /// Stores the time each value was written alongside it, for
+    /// [`RuskStore::get_with_age`]. Disabled by default.
+    ///
+    /// Timestamps are kept through compaction and [`RuskStore::rename`].
+    /// Values written while this was disabled have no timestamp, and their
+    /// age reads as unknown.
+    pub fn record_created_at(mut self, enabled: bool) -> Self {
+        self.options.record_created_at = enabled;
+        self
+    }
+
+    /// Keeps the `count` most recent values of each live key through
+    /// compaction, readable with [`RuskStore::get_version`]. Defaults to 1,
+    /// keeping only the current value; `0` is treated as 1.
+    ///
+    /// Retained older values don't count as dead space, so they don't
+    /// trigger compaction. Removing a key discards all its versions. The
+    /// hint file can't describe retained versions, so with more than one
+    /// version the log is always replayed in full on open and
+    /// [`index_cache`](Self::index_cache) has no effect.
+    pub fn versions_retained(mut self, count: usize) -> Self {
+        self.options.versions_retained = count.max(1);
+        self
+    }
+
+    /// Sets whether `get` verifies each record's checksum before returning
+    /// its value, failing with `RuskError::ChecksumMismatch` on corruption.
+    ///
+    /// Defaults to `true`. Disabling it trades corruption detection for
+    /// slightly cheaper reads. Replay on open always verifies checksums.
+    pub fn verify_on_read(mut self, verify: bool) -> Self {
+        self.options.verify_on_read = verify;
+        self
+    }
+
+    /// Also writes the index to the hint file when the store is dropped, so
+    /// the next open only replays records written after that point.
+    ///
+    /// The hint file is always written by compaction and consulted on open;
+    /// it is validated against the log's compaction generation and size, and
+    /// ignored in favour of a full replay if it doesn't match. Keys
+    /// remembered by [`track_removed`](Self::track_removed) are not cached.
+    pub fn index_cache(mut self, enabled: bool) -> Self {
+        self.options.index_cache = enabled;
+        self
+    }
+
+    /// Keeps a copy of the log in the directory `dir`, under the same file
+    /// name, and appends every write to it before it reaches the log.
+    ///
+    /// If the log fails to replay on open because it is corrupt, it is
+    /// moved aside with a `.corrupt` extension and replaced by the mirror.
+    /// Compaction rewrites the mirror along with the log, and on open a
+    /// mirror that doesn't match the log is rewritten from it. `dir` should
+    /// be on a different disk from the store to be of much use.
+    pub fn mirror_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.mirror_path = Some(dir.into());
+        self
+    }
+
+    /// Sets what happens when writing to the mirror fails. Defaults to
+    /// [`MirrorPolicy::Fatal`]. Has no effect without
+    /// [`mirror_path`](Self::mirror_path).
+    pub fn mirror_policy(mut self, policy: MirrorPolicy) -> Self {
+        self.options.mirror_policy = policy;
+        self
+    }
+
+    /// Sets what [`RuskStore::increment`] does when the existing value isn't
+    /// a valid `i64`. Defaults to [`InvalidCounterPolicy::Error`].
+    pub fn invalid_counter(mut self, policy: InvalidCounterPolicy) -> Self {
+        self.options.invalid_counter = policy;
+        self
+    }
+
+    /// Sets whether reads fail when the index points a key at a tombstone,
+    /// which means the index and the log have diverged.
+    ///
+    /// Defaults to `true`: `get` and friends fail with
+    /// `RuskError::IndexMismatch`, naming the key and the tombstone's offset.
+    /// When disabled, the bogus index entry is dropped with a warning and the
+    /// key reads as missing.
+    pub fn strict_reads(mut self, strict: bool) -> Self {
+        self.options.strict_reads = strict;
+        self
+    }
+
+    /// Sets what [`RuskStore::import_ndjson`] does with a line that isn't a
+    /// valid entry. Defaults to [`ImportErrorPolicy::Skip`].
+    pub fn import_errors(mut self, policy: ImportErrorPolicy) -> Self {
+        self.options.import_errors = policy;
+        self
+    }
+
+    /// Retries writes and flushes of the log and hint file up to `retries`
+    /// times, backing off between attempts, when they fail with a transient error
+    /// (`Interrupted` or `WouldBlock`), as can happen on network
+    /// filesystems.
+    ///
+    /// Defaults to 0, which adds no retries. Other errors, such as a full
+    /// disk, are never retried, and a retried write resumes after the bytes
+    /// already written rather than writing the record again.
+    pub fn io_retries(mut self, retries: usize) -> Self {
+        self.options.io_retries = retries;
+        self
+    }
+
+    /// Keeps up to `capacity` recently read values in memory, evicting the
+    /// least recently used. Disabled by default.
+    ///
+    /// [`RuskStore::get`] serves cached values without reading the log, and
+    /// [`RuskStore::get_cached`] serves them through a shared borrow. Writes
+    /// to a key drop its cached value. [`RuskStore::cache_stats`] reports
+    /// how often lookups hit the cache.
+    pub fn value_cache(mut self, capacity: usize) -> Self {
+        self.options.value_cache = Some(capacity);
+        self
+    }
+
+    /// When the store is opened with [`open_handle`](Self::open_handle),
+    /// checks the dead space every `interval` and compacts if it exceeds the
+    /// [`compaction_threshold`](Self::compaction_threshold), so space is
+    /// reclaimed even while no writes arrive to trigger compaction.
+    ///
+    /// Disabled by default. A store with no more dead space than the
+    /// threshold is never compacted, and a compaction already running on
+    /// another handle is skipped rather than waited for. The check runs on
+    /// the handle's thread between requests, and the timer thread driving it
+    /// stops when the last handle is dropped.
+    ///
+    /// A plain `RuskStore` has no thread to run the check on, so
+    /// [`open`](Self::open) and [`open_with_hasher`](Self::open_with_hasher)
+    /// return `RuskError::AutoCompactRequiresHandle` if this is set.
+    pub fn auto_compact_interval(mut self, interval: Duration) -> Self {
+        self.options.auto_compact_interval = Some(interval);
+        self
+    }
+
+    /// Opens the store with the configured options.
+    pub fn open(self) -> Result<RuskStore> {
+        self.check_no_auto_compact()?;
+        RuskStore::open_with_options(self.path, self.options)
+    }
+
+    /// Opens the store with the configured options, using `hasher` for the
+    /// in-memory index instead of the default SipHash.
+    ///
+    /// A faster, non-keyed hasher speeds up index operations on stores with
+    /// many keys, but lets whoever chooses the keys force hash collisions;
+    /// only use one when keys come from a trusted source. The hasher only
+    /// affects memory, so a store can be opened with a different hasher each
+    /// time.
+    pub fn open_with_hasher<S: BuildHasher + Clone>(self, hasher: S) -> Result<RuskStore<S>> {
+        self.check_no_auto_compact()?;
+        RuskStore::open_with_hasher(self.path, self.options, hasher)
+    }
+
+    /// Opens the store with the configured options and moves it onto a
+    /// [`StoreHandle`]'s thread, which also runs the
+    /// [`auto_compact_interval`](Self::auto_compact_interval) if one is set.
+    pub fn open_handle(self) -> Result<StoreHandle> {
+        let store = RuskStore::open_with_options(self.path, self.options)?;
+        Ok(StoreHandle::new(store))
+    }
+
+    fn check_no_auto_compact(&self) -> Result<()> {
+        if self.options.auto_compact_interval.is_some() {
+            return Err(RuskError::AutoCompactRequiresHandle);
+        }
+        Ok(())
+    }
+}
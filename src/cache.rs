@@ -0,0 +1,129 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Counters describing how well the value cache is working. See
+/// [`RuskStore::cache_stats`](crate::RuskStore::cache_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups served from the cache.
+    pub hits: u64,
+    /// Lookups that had to go to the log.
+    pub misses: u64,
+    /// Values dropped to make room for others. Values dropped because their
+    /// key was written aren't counted.
+    pub evictions: u64,
+    /// Number of values currently cached.
+    pub size: usize,
+    /// Most values the cache holds, or `None` if caching is disabled, in
+    /// which case every other field is zero.
+    pub capacity: Option<usize>,
+}
+
+/// A bounded cache of recently read values, evicting the least recently
+/// used once `capacity` is exceeded.
+///
+/// Like [`RecentlyRemoved`](crate::removed::RecentlyRemoved), each use is
+/// stamped so that stale queue entries (for values that were used again
+/// since) never evict a more recent one.
+#[derive(Debug)]
+pub(crate) struct ValueCache {
+    capacity: usize,
+    entries: HashMap<String, (String, u64)>,
+    order: VecDeque<(String, u64)>,
+    next_stamp: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl ValueCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ValueCache {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            next_stamp: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Returns the cached value of `key`, marking it as most recently used.
+    pub(crate) fn get(&mut self, key: &str) -> Option<String> {
+        let stamp = self.next_stamp;
+        let Some((value, entry_stamp)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        *entry_stamp = stamp;
+        let value = value.clone();
+
+        self.hits += 1;
+        self.next_stamp += 1;
+        self.order.push_back((key.to_owned(), stamp));
+        self.drop_stale();
+        Some(value)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, value: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.entries.insert(key.clone(), (value, stamp));
+        self.order.push_back((key, stamp));
+
+        while self.entries.len() > self.capacity {
+            self.evict_oldest();
+        }
+        self.drop_stale();
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            size: self.entries.len(),
+            capacity: Some(self.capacity),
+        }
+    }
+
+    pub(crate) fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn evict_oldest(&mut self) {
+        while let Some((key, stamp)) = self.order.pop_front() {
+            if self.entries.get(&key).map(|(_, current)| *current) == Some(stamp) {
+                self.entries.remove(&key);
+                self.evictions += 1;
+                return;
+            }
+        }
+    }
+
+    /// Stale entries accumulate with every hit; drop them before the queue
+    /// grows much past the cached set.
+    fn drop_stale(&mut self) {
+        if self.order.len() > self.capacity * 2 {
+            let entries = &self.entries;
+            self.order.retain(|(key, stamp)| {
+                entries.get(key).map(|(_, current)| *current) == Some(*stamp)
+            });
+        }
+    }
+}
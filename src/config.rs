@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::builder::{DEFAULT_BUFFER_SIZE, DEFAULT_COMPACTION_THRESHOLD, DurabilityMode};
+use crate::engine::LOG_FILE_NAME;
+
+/// Store settings that can be loaded from a configuration file.
+///
+/// Missing fields take their default values and unknown fields are ignored,
+/// so a config only needs to mention what it changes. See
+/// [`RuskStore::open_with_config`](crate::RuskStore::open_with_config); the
+/// fields correspond to the [`RuskStoreBuilder`](crate::RuskStoreBuilder)
+/// methods of the same name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuskConfig {
+    pub compaction_threshold: u64,
+    pub durability: DurabilityMode,
+    pub write_buffer_size: usize,
+    pub read_buffer_size: usize,
+    pub log_file_name: String,
+}
+
+impl Default for RuskConfig {
+    fn default() -> Self {
+        RuskConfig {
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            durability: DurabilityMode::default(),
+            write_buffer_size: DEFAULT_BUFFER_SIZE,
+            read_buffer_size: DEFAULT_BUFFER_SIZE,
+            log_file_name: LOG_FILE_NAME.to_owned(),
+        }
+    }
+}
@@ -1,259 +1,3142 @@
-use std::collections::HashMap;
-use std::fs::{self, File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Take, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::builder::{
+    DurabilityMode, FlushMode, ImportErrorPolicy, InvalidCounterPolicy, MirrorPolicy, Options,
+    RuskStoreBuilder,
+};
+use crate::cache::{CacheStats, ValueCache};
+use crate::config::RuskConfig;
+use crate::error::{Result, RuskError};
+use crate::format::{self, Command, Header, Record};
+use crate::hint::{Hint, HintEntry};
+use crate::memory::MemoryStore;
+use crate::reader::RuskReader;
+use crate::removed::RecentlyRemoved;
+use crate::repair::{self, RepairPolicy, RepairReport};
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::error::{Result, RuskError};
+pub(crate) const LOG_FILE_NAME: &str = "data.log";
+// Files alongside the log are named after it, with these extensions.
+const COMPACTION_EXTENSION: &str = "compact";
+const COMPACTION_LOCK_EXTENSION: &str = "compact.lock";
+const HINT_EXTENSION: &str = "hint";
+/// Records the highest sequence number the last compaction may have dropped.
+const COMPACTED_SEQ_EXTENSION: &str = "compacted";
+/// A log that failed to replay is moved aside with this extension when it is
+/// replaced by its mirror.
+const CORRUPT_EXTENSION: &str = "corrupt";
+/// The mirror is rewritten into this file alongside it, then renamed over it.
+const MIRROR_TMP_EXTENSION: &str = "mirror";
+/// How many records [`RuskStore::probe`] reads to estimate the record count.
+const PROBE_SAMPLE_RECORDS: u64 = 64;
+/// How many bytes of log replay between progress callbacks.
+const PROGRESS_INTERVAL: u64 = 4 * 1024 * 1024;
+/// Delay before the first retry of a transient IO error; it doubles with
+/// each further retry.
+const IO_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CommandPos {
+    pub(crate) offset: u64,
+    pub(crate) length: u64,
+    pub(crate) seq: u64,
+}
 
-const LOG_FILE_NAME: &str = "data.log";
-const COMPACTION_THRESHOLD: u64 = 1024 * 1024; // 1MB threshold for compaction
+/// A value together with the location and version of its record in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub value: String,
+    /// Byte offset of the record in the log.
+    pub offset: u64,
+    /// Length of the record on disk, including its framing.
+    pub length: u64,
+    /// Sequence number of the write. Records written before sequence
+    /// numbers were introduced report `0`.
+    pub seq: u64,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-enum Command {
-    Set { key: String, value: String },
-    Remove { key: String },
+/// A record for a key as found in the log. See [`RuskStore::history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Byte offset of the record in the log.
+    pub offset: u64,
+    /// Sequence number of the write.
+    pub seq: u64,
+    pub command: Command,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct CommandPos {
-    offset: u64,
-    length: u64,
+/// What a compaction did. See [`RuskStore::compact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Size of the log before compaction, in bytes.
+    pub bytes_before: u64,
+    /// Size of the compacted log, in bytes.
+    pub bytes_after: u64,
+    /// Number of live keys written to the compacted log.
+    pub live_keys: usize,
+    /// Compaction generation of the new log.
+    pub generation: u64,
+    /// Number of live keys removed to fit a size budget. Only
+    /// [`RuskStore::compact_to_budget`] ever evicts keys.
+    pub keys_evicted: usize,
+}
+
+impl CompactionReport {
+    /// Returns the number of bytes the compaction reclaimed.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Counters of the bytes written to the log by a store handle since it was
+/// opened. See [`RuskStore::metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Bytes of records appended by writes.
+    pub bytes_written_by_user: u64,
+    /// Bytes written to the log in total: appended records plus the logs
+    /// rewritten by compaction.
+    pub bytes_written_total: u64,
+}
+
+impl Metrics {
+    /// Returns the write amplification: total bytes written per byte
+    /// written by the user, or `None` if nothing has been written.
+    pub fn write_amplification(&self) -> Option<f64> {
+        (self.bytes_written_by_user > 0)
+            .then(|| self.bytes_written_total as f64 / self.bytes_written_by_user as f64)
+    }
+}
+
+/// Counts of live entries by size. See [`RuskStore::size_histogram`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeHistogram {
+    /// Entries smaller than 64 bytes.
+    pub under_64b: u64,
+    /// Entries of at least 64 bytes but smaller than 1 KiB.
+    pub under_1kib: u64,
+    /// Entries of at least 1 KiB but smaller than 1 MiB.
+    pub under_1mib: u64,
+    /// Entries of 1 MiB or more.
+    pub larger: u64,
+}
+
+impl SizeHistogram {
+    fn add(&mut self, size: u64) {
+        match size {
+            0..64 => self.under_64b += 1,
+            64..1024 => self.under_1kib += 1,
+            1024..1_048_576 => self.under_1mib += 1,
+            _ => self.larger += 1,
+        }
+    }
+}
+
+/// What compacting the log would reclaim. See
+/// [`RuskStore::compaction_estimate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionEstimate {
+    /// Size of the log, in bytes.
+    pub current_size: u64,
+    /// Expected size of the log after compaction, in bytes.
+    pub estimated_size: u64,
+    /// Bytes compaction is expected to free.
+    pub reclaimable: u64,
+}
+
+/// A quick summary of a store on disk. See [`RuskStore::probe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeReport {
+    /// Format version of the log.
+    pub version: u32,
+    /// Compaction generation of the log.
+    pub generation: u64,
+    /// Size of the log, in bytes.
+    pub log_size: u64,
+    /// Number of records in the log, extrapolated from the size of the first
+    /// few. Includes superseded records and tombstones.
+    pub estimated_records: u64,
+}
+
+/// How far replay has got through the log. See
+/// [`RuskStore::open_with_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Offset in the log replay has reached.
+    pub bytes_read: u64,
+    /// Size of the log.
+    pub total_bytes: u64,
+}
+
+/// Whether a write compacted the log. See
+/// [`RuskStore::last_auto_compaction`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AutoCompaction {
+    /// No compaction was needed.
+    #[default]
+    NotAttempted,
+    /// The log was compacted.
+    Completed,
+    /// Compaction was needed but another compaction was already running.
+    Skipped,
+    /// Compaction was attempted and failed; the write returned the error.
+    Failed,
+}
+
+/// The most recent write made through a store. See
+/// [`RuskStore::last_write`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastWrite {
+    /// Key written, after any configured key transform.
+    pub key: String,
+    pub kind: WriteKind,
+    /// Byte offset of the record in the log it was written to.
+    pub offset: u64,
+    /// Sequence number of the write.
+    pub seq: u64,
+    /// Compaction generation of the log the record was written to. If this
+    /// is older than the store's current generation, `offset` no longer
+    /// points into the live log.
+    pub generation: u64,
+}
+
+/// Which kind of record a write appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    Set,
+    Remove,
+}
+
+/// Whether a key is present, and if not, whether it is known to have been
+/// removed. See [`RuskStore::get_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// The key currently has a value.
+    Present,
+    /// The key was recently removed.
+    Removed,
+    /// The key has no value, and was either never set or removed too long
+    /// ago to be remembered.
+    Unknown,
+}
+
+/// Which value wins when both stores in [`RuskStore::merge_from`] have a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing value with the other store's.
+    Overwrite,
+    /// Keep the existing value.
+    KeepExisting,
+}
+
+/// What a merge did. See [`RuskStore::merge_from`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Keys that only the other store had.
+    pub added: usize,
+    /// Existing keys given the other store's value.
+    pub overwritten: usize,
+    /// Keys in both stores whose existing value was kept.
+    pub skipped: usize,
+}
+
+/// What an NDJSON import did. See [`RuskStore::import_ndjson`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Lines whose entry was set.
+    pub imported: usize,
+    /// Line numbers, counting from 1, that weren't a valid entry and were
+    /// skipped.
+    pub failed_lines: Vec<usize>,
+}
+
+/// One line of [`RuskStore::export_ndjson`] output or
+/// [`RuskStore::import_ndjson`] input.
+#[derive(Serialize, Deserialize)]
+struct NdjsonEntry<T> {
+    key: T,
+    value: T,
 }
 
 /// The Bitcask-style key-value store engine.
 /// Each entry on disk is written as:
 /// ```text
-/// [4 bytes: length (u32 big-endian)] [N bytes: JSON-serialized Command]
+/// [4 bytes: length (u32 big-endian)] [4 bytes: CRC32] [N bytes: encoded command]
 /// ```
-pub struct RuskStore {
+///
+/// See [`crate::format`] for the header and the record encodings.
+///
+/// `S` is the hasher used by the in-memory index. The default, SipHash, is
+/// resistant to collision attacks from untrusted keys; for trusted keys a
+/// faster hasher can be chosen with [`RuskStoreBuilder::open_with_hasher`].
+pub struct RuskStore<S: BuildHasher + Clone = RandomState> {
     path: PathBuf,
-    index: HashMap<String, CommandPos>,
-    writer: BufWriter<File>,
+    index: HashMap<String, CommandPos, S>,
+    writer: Arc<Mutex<BufWriter<File>>>,
+    flusher: Option<Flusher>,
+    /// Set by [`close`](RuskStore::close) so dropping doesn't shut down twice.
+    closed: bool,
     current_pos: u64,
+    /// Records written since the write buffer was last empty, for
+    /// [`FlushMode::Batched`].
+    pending_writes: usize,
+    /// Bytes in the log that the index no longer points at: every superseded
+    /// `Set` and every tombstone. Compaction reclaims exactly this much, so
+    /// replay and the write paths must keep it in step with the index.
     uncompacted: u64,
+    /// Number of `Remove` records in the log.
+    tombstones: u64,
+    seq: u64,
+    /// Highest sequence number whose record compaction may have dropped, or
+    /// `0` if the log has never been compacted.
+    compacted_seq: u64,
+    version: u32,
+    generation: u64,
+    removed: Option<RecentlyRemoved>,
+    /// Recently read values, when [`value_cache`] is enabled. Behind a mutex
+    /// so hits can be served through a shared borrow.
+    ///
+    /// [`value_cache`]: RuskStoreBuilder::value_cache
+    cache: Option<Mutex<ValueCache>>,
+    last_auto_compaction: AutoCompaction,
+    last_write: Option<LastWrite>,
+    metrics: Metrics,
+    /// Superseded `Set`s kept for each live key, oldest first, when more
+    /// than one version is retained. Not counted as uncompacted.
+    versions: HashMap<String, VecDeque<CommandPos>>,
+    /// Reader reused by `get` and friends, tagged with the generation of
+    /// the log it was opened on.
+    cached_reader: Option<CachedReader>,
+    mirror: Option<Mirror>,
+    options: Options,
+}
+
+#[derive(Debug)]
+struct CachedReader {
+    generation: u64,
+    reader: BufReader<File>,
+}
+
+/// The second copy of the log written by
+/// [`mirror_path`](RuskStoreBuilder::mirror_path).
+#[derive(Debug)]
+struct Mirror {
+    path: PathBuf,
+    /// Open for appending while the mirror matches the log; `None` once it
+    /// has fallen behind and must be copied from the log again.
+    file: Option<File>,
+    /// Length of the mirror when it matches the log.
+    len: u64,
+}
+
+/// Reader returned by [`RuskStore::open_value`].
+enum ValueReader {
+    /// Streams the value straight from the log.
+    Stream(Take<File>),
+    /// Serves a value that had to be decoded in full.
+    Buffered(Cursor<Vec<u8>>),
+}
+
+impl Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ValueReader::Stream(reader) => reader.read(buf),
+            ValueReader::Buffered(reader) => reader.read(buf),
+        }
+    }
 }
 
 impl RuskStore {
+    /// Opens the store at `path`, creating it if it doesn't exist.
+    ///
+    /// Equivalent to [`RuskStore::open_or_create`].
     pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_or_create(path)
+    }
+
+    /// Opens the store at `path`, creating the directory and log if missing.
+    pub fn open_or_create(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with_options(path.into(), Options::default())
+    }
+
+    /// Opens an existing store at `path`.
+    ///
+    /// Returns `RuskError::NotFound` if the directory doesn't already contain
+    /// a log, instead of silently starting an empty store.
+    pub fn open_existing(path: impl Into<PathBuf>) -> Result<Self> {
         let path = path.into();
-        fs::create_dir_all(&path)?;
 
+        if !check_log_file(&path.join(LOG_FILE_NAME))? {
+            return Err(RuskError::NotFound);
+        }
+
+        Self::open_with_options(path, Options::default())
+    }
+
+    /// Creates a new store at `path`.
+    ///
+    /// Returns `RuskError::AlreadyExists` if the directory already contains a
+    /// non-empty log, so an existing dataset is never appended to by mistake.
+    pub fn create_new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
         let log_path = path.join(LOG_FILE_NAME);
 
+        if fs::metadata(&log_path).is_ok_and(|metadata| metadata.len() > format::MAX_HEADER_LEN) {
+            return Err(RuskError::AlreadyExists);
+        }
+
+        Self::open(path)
+    }
+
+    /// Creates an empty store that keeps its data in memory, with no log
+    /// file. See [`MemoryStore`] for why it is a separate type, and
+    /// [`MemoryStore::bounded`] for one limited to a number of keys.
+    pub fn in_memory() -> MemoryStore {
+        MemoryStore::new()
+    }
+
+    /// Opens an existing store, compacts it and closes it again.
+    ///
+    /// This is a one-shot maintenance operation, e.g. for a cleanup job. Like
+    /// [`open_existing`](Self::open_existing), it returns
+    /// `RuskError::NotFound` rather than creating a store that doesn't exist.
+    pub fn open_and_compact(path: impl Into<PathBuf>) -> Result<CompactionReport> {
+        Self::open_existing(path)?.compact()
+    }
+
+    /// Opens the store at `path` with settings from a [`RuskConfig`],
+    /// creating it if it doesn't exist.
+    pub fn open_with_config(path: impl Into<PathBuf>, config: RuskConfig) -> Result<Self> {
+        Self::builder(path)
+            .compaction_threshold(config.compaction_threshold)
+            .durability(config.durability)
+            .write_buffer_size(config.write_buffer_size)
+            .read_buffer_size(config.read_buffer_size)
+            .log_file_name(config.log_file_name)
+            .open()
+    }
+
+    /// Salvages the readable records of the store at `src` into a new store
+    /// at `dest`, e.g. after opening `src` failed with a checksum or
+    /// corruption error.
+    ///
+    /// Every record whose checksum verifies is copied, including superseded
+    /// ones; `policy` decides whether to stop at the first unreadable record
+    /// or skip past it. The result is a fresh log that can be opened
+    /// normally. `src` is never modified, and `dest` must not already
+    /// contain a store (`RuskError::AlreadyExists`).
+    pub fn repair(
+        src: impl AsRef<Path>,
+        dest: impl AsRef<Path>,
+        policy: RepairPolicy,
+    ) -> Result<RepairReport> {
+        let dest = dest.as_ref();
+        fs::create_dir_all(dest)?;
+        repair::repair_log(
+            &src.as_ref().join(LOG_FILE_NAME),
+            &dest.join(LOG_FILE_NAME),
+            policy,
+        )
+    }
+
+    /// Opens the store at `path` like [`open`](Self::open), calling
+    /// `progress` as the log is replayed.
+    ///
+    /// The callback fires every few megabytes of log read, and once more when
+    /// replay finishes. If a hint file is used, replay (and so progress)
+    /// starts from the end of the hinted portion of the log.
+    pub fn open_with_progress(
+        path: impl Into<PathBuf>,
+        mut progress: impl FnMut(ReplayProgress),
+    ) -> Result<Self> {
+        Self::open_with(
+            path.into(),
+            Options::default(),
+            RandomState::new(),
+            &mut progress,
+        )
+    }
+
+    /// Opens the store at `path`, creating it if it doesn't exist, and sets
+    /// each entry of `seed` whose key isn't already present.
+    ///
+    /// Existing values are never overwritten, so seeding an already
+    /// populated store is harmless. The missing entries are written as one
+    /// batch with a single flush.
+    pub fn open_with_seed(path: impl Into<PathBuf>, seed: HashMap<String, String>) -> Result<Self> {
+        let mut store = Self::open(path)?;
+        let missing: Vec<_> = seed
+            .into_iter()
+            .filter(|(key, _)| {
+                !store
+                    .index
+                    .contains_key(store.normalize_key_ref(key).as_ref())
+            })
+            .collect();
+        if !missing.is_empty() {
+            store.set_batch(missing)?;
+        }
+        Ok(store)
+    }
+
+    /// Checks that the store at `path` looks openable without replaying it.
+    ///
+    /// This verifies that the directory and its log exist and that the log
+    /// is a regular file, reads the header, and estimates the number of
+    /// records from the log size and the first few records. Nothing is
+    /// written, and the cost doesn't grow with the size of the log. Returns
+    /// `RuskError::NotFound` if there is no store at `path`.
+    pub fn probe(path: impl AsRef<Path>) -> Result<ProbeReport> {
+        let log_path = path.as_ref().join(LOG_FILE_NAME);
+        if !check_log_file(&log_path)? {
+            return Err(RuskError::NotFound);
+        }
+
+        let file = File::open(&log_path)?;
+        let log_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut bytes = Vec::with_capacity(format::MAX_HEADER_LEN as usize);
+        (&mut reader)
+            .take(format::MAX_HEADER_LEN)
+            .read_to_end(&mut bytes)?;
+        let header = format::decode_header(&bytes).unwrap_or(Header {
+            version: format::LEGACY_VERSION,
+            generation: 0,
+        });
+        format::check_version(header.version)?;
+
+        let data_start = format::data_start(header.version);
+        reader.seek(SeekFrom::Start(data_start))?;
+        let mut pos = data_start;
+        let mut sampled = 0;
+        while sampled < PROBE_SAMPLE_RECORDS && pos < log_size {
+            let frame = format::read_frame(&mut reader, header.version, log_size - pos)?;
+            format::decode_frame(&frame, header.version, true)?;
+            pos += frame.len() as u64;
+            sampled += 1;
+        }
+
+        let estimated_records = match pos - data_start {
+            0 => 0,
+            sampled_bytes => (log_size - data_start) * sampled / sampled_bytes,
+        };
+
+        Ok(ProbeReport {
+            version: header.version,
+            generation: header.generation,
+            log_size,
+            estimated_records,
+        })
+    }
+
+    /// Opens a read-only view of a log read through `file`, without needing
+    /// its path: e.g. a `File` passed down by a parent process, or a
+    /// `Cursor` over a log embedded with `include_bytes!` so tests never
+    /// touch the filesystem.
+    ///
+    /// The log is replayed from the start of `file` to build the index, and
+    /// the returned [`RuskReader`] reads values through it. Writes and
+    /// compaction need the store's directory, so they are only available
+    /// through the path-based constructors. Since nothing is written, a
+    /// record torn at the end of the log is ignored rather than cut off.
+    pub fn from_file<R: Read + Seek + Send + 'static>(file: R) -> Result<RuskReader> {
+        let mut reader = BufReader::new(file);
+        let file_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut bytes = Vec::with_capacity(format::MAX_HEADER_LEN as usize);
+        (&mut reader)
+            .take(format::MAX_HEADER_LEN)
+            .read_to_end(&mut bytes)?;
+        let header = match format::decode_header(&bytes) {
+            Some(header) => header,
+            None if bytes.len() as u64 == file_len && format::is_torn_header(&bytes) => Header {
+                version: format::CURRENT_VERSION,
+                generation: 0,
+            },
+            None => Header {
+                version: format::LEGACY_VERSION,
+                generation: 0,
+            },
+        };
+        format::check_version(header.version)?;
+
+        let mut index = HashMap::new();
+        let overhead = format::frame_overhead(header.version);
+        let mut pos = format::data_start(header.version).min(file_len);
+        reader.seek(SeekFrom::Start(pos))?;
+        while pos < file_len {
+            let frame = match file_len - pos {
+                remaining if remaining < overhead => None,
+                remaining => match format::read_frame(&mut reader, header.version, remaining) {
+                    Ok(frame) => Some(frame),
+                    Err(RuskError::Corruption { .. }) => None,
+                    Err(err) => return Err(err),
+                },
+            };
+            let Some(frame) = frame else {
+                warn!("ignoring truncated record at offset {}", pos);
+                break;
+            };
+
+            let record = format::decode_frame(&frame, header.version, true)?;
+            let length = frame.len() as u64;
+            match record.command {
+                Command::Set { key, .. } => {
+                    let cmd_pos = CommandPos {
+                        offset: pos,
+                        length,
+                        seq: record.seq,
+                    };
+                    index.insert(key, cmd_pos);
+                }
+                Command::Remove { key } => {
+                    index.remove(&key);
+                }
+            }
+            pos += length;
+        }
+
+        Ok(RuskReader::new(
+            Box::new(reader),
+            index,
+            header.version,
+            true,
+            None,
+        ))
+    }
+
+    /// Returns a builder for opening a store with non-default options.
+    pub fn builder(path: impl Into<PathBuf>) -> RuskStoreBuilder {
+        RuskStoreBuilder::new(path)
+    }
+
+    pub(crate) fn open_with_options(path: PathBuf, options: Options) -> Result<Self> {
+        Self::open_with(path, options, RandomState::new(), &mut |_| {})
+    }
+}
+
+impl<S: BuildHasher + Clone> RuskStore<S> {
+    pub(crate) fn open_with_hasher(path: PathBuf, options: Options, hasher: S) -> Result<Self> {
+        Self::open_with(path, options, hasher, &mut |_| {})
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "rusk.open",
+            skip_all,
+            fields(path = %path.display(), records = tracing::field::Empty)
+        )
+    )]
+    fn open_with(
+        path: PathBuf,
+        options: Options,
+        hasher: S,
+        progress: &mut dyn FnMut(ReplayProgress),
+    ) -> Result<Self> {
+        let mirror_path = options
+            .mirror_path
+            .as_ref()
+            .map(|dir| dir.join(&options.log_file_name));
+
+        let mut store =
+            match Self::open_log(path.clone(), options.clone(), hasher.clone(), progress) {
+                Err(
+                    err @ (RuskError::ChecksumMismatch
+                    | RuskError::Corruption { .. }
+                    | RuskError::Serde(_)),
+                ) => {
+                    let Some(mirror_path) = mirror_path.as_ref().filter(|path| path.is_file())
+                    else {
+                        return Err(err);
+                    };
+                    let log_path = path.join(&options.log_file_name);
+                    warn!(
+                        "log {} is corrupt ({}), restoring it from mirror {}",
+                        log_path.display(),
+                        err,
+                        mirror_path.display()
+                    );
+                    fs::rename(&log_path, log_path.with_extension(CORRUPT_EXTENSION))?;
+                    fs::copy(mirror_path, &log_path)?;
+                    // The hint described the corrupt log.
+                    let _ = fs::remove_file(log_path.with_extension(HINT_EXTENSION));
+                    Self::open_log(path, options, hasher, progress)?
+                }
+                result => result?,
+            };
+
+        if let Some(path) = mirror_path {
+            store.attach_mirror(path)?;
+        }
+        Ok(store)
+    }
+
+    /// Opens the log and replays it into the index, without the mirror.
+    fn open_log(
+        path: PathBuf,
+        options: Options,
+        hasher: S,
+        progress: &mut dyn FnMut(ReplayProgress),
+    ) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+
+        let log_path = path.join(&options.log_file_name);
+        check_log_file(&log_path)?;
+
         let writer_file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&log_path)?;
 
+        let writer = Arc::new(Mutex::new(BufWriter::with_capacity(
+            options.write_buffer_size,
+            writer_file,
+        )));
+        let flusher = match (options.flush_mode, options.flush_interval) {
+            (FlushMode::Manual, Some(interval)) => Some(Flusher::spawn(&writer, interval)),
+            (FlushMode::Batched(policy), _) => Some(Flusher::spawn(&writer, policy.max_delay)),
+            _ => None,
+        };
+
         let mut store = RuskStore {
             path,
-            index: HashMap::new(),
-            writer: BufWriter::new(writer_file),
+            index: HashMap::with_hasher(hasher),
+            writer,
+            flusher,
+            closed: false,
             current_pos: 0,
+            pending_writes: 0,
             uncompacted: 0,
+            tombstones: 0,
+            seq: 0,
+            compacted_seq: 0,
+            version: format::CURRENT_VERSION,
+            generation: 0,
+            removed: options.track_removed.map(RecentlyRemoved::new),
+            cache: options
+                .value_cache
+                .map(|capacity| Mutex::new(ValueCache::new(capacity))),
+            last_auto_compaction: AutoCompaction::NotAttempted,
+            last_write: None,
+            metrics: Metrics::default(),
+            versions: HashMap::new(),
+            cached_reader: None,
+            mirror: None,
+            options,
         };
 
-        store.replay_log()?;
+        store.load_header()?;
+        let data_start = format::data_start(store.version);
+        let records = match store.load_hint()? {
+            Some(hint_len) => match store.replay_log(hint_len, progress) {
+                Ok(records) => {
+                    debug!("loaded index from hint, replayed {} newer records", records);
+                    records
+                }
+                Err(err) => {
+                    warn!(
+                        "hint is inconsistent with the log ({}), replaying full log",
+                        err
+                    );
+                    store.reset_index();
+                    store.replay_log(data_start, progress)?
+                }
+            },
+            None => store.replay_log(data_start, progress)?,
+        };
+        store.compacted_seq = store.load_compacted_seq();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("records", records);
+        info!(
+            "opened store at {} with {} records replayed ({} live keys)",
+            store.path.display(),
+            records,
+            store.index.len()
+        );
 
         Ok(store)
     }
 
-    fn replay_log(&mut self) -> Result<()> {
-        let log_path = self.path.join(LOG_FILE_NAME);
+    /// Reads the log header to determine the format version and generation,
+    /// writing a fresh header if the log is empty.
+    fn load_header(&mut self) -> Result<()> {
+        let log_path = self.log_path();
+        let mut bytes = Vec::with_capacity(format::MAX_HEADER_LEN as usize);
+        File::open(&log_path)?
+            .take(format::MAX_HEADER_LEN)
+            .read_to_end(&mut bytes)?;
+
+        // A partial header can only come from a crash while creating the log,
+        // before any record was written.
+        let torn = format::is_torn_header(&bytes);
+
+        let header = if bytes.is_empty() || torn {
+            if torn {
+                warn!("discarding torn header in {}", log_path.display());
+                OpenOptions::new().write(true).open(&log_path)?.set_len(0)?;
+            }
+            let header = Header {
+                version: self.options.encoding.version(),
+                generation: 0,
+            };
+            let mut writer = self.writer();
+            writer.write_all(&format::encode_header(&header))?;
+            writer.flush()?;
+            header
+        } else {
+            format::decode_header(&bytes).unwrap_or(Header {
+                version: format::LEGACY_VERSION,
+                generation: 0,
+            })
+        };
+        format::check_version(header.version)?;
+
+        self.version = header.version;
+        self.generation = header.generation;
+        self.current_pos = format::data_start(self.version);
+        Ok(())
+    }
+
+    /// Reads the sequence number recorded by the last compaction.
+    ///
+    /// If the log has been compacted but the record is missing or from
+    /// another generation, there is no telling what was dropped, so
+    /// everything written before the store was opened is treated as
+    /// compacted.
+    fn load_compacted_seq(&self) -> u64 {
+        if self.generation == 0 {
+            return 0;
+        }
+        let recorded = fs::read_to_string(self.sidecar_path(COMPACTED_SEQ_EXTENSION))
+            .ok()
+            .and_then(|contents| {
+                let (generation, seq) = contents.trim().split_once(' ')?;
+                Some((generation.parse::<u64>().ok()?, seq.parse::<u64>().ok()?))
+            });
+        match recorded {
+            Some((generation, seq)) if generation == self.generation => seq,
+            _ => self.seq,
+        }
+    }
+
+    /// Records the sequence number the last compaction reached, with the
+    /// generation it produced.
+    fn save_compacted_seq(&self) -> Result<()> {
+        let path = self.sidecar_path(COMPACTED_SEQ_EXTENSION);
+        let tmp_path = path.with_extension("compacted.tmp");
+        fs::write(
+            &tmp_path,
+            format!("{} {}\n", self.generation, self.compacted_seq),
+        )?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Loads the index from the hint file, returning the log length the hint
+    /// covers, or `None` if there is no usable hint.
+    ///
+    /// A hint is usable if its checksum matches, it was written in the log's
+    /// current generation, doesn't extend past the end of the log, and none
+    /// of its entries point past the part of the log it covers. Anything else
+    /// is ignored in favour of a full replay, never an error. Within a
+    /// generation the log is append-only, so the records after the hinted
+    /// length still need to be replayed.
+    fn load_hint(&mut self) -> Result<Option<u64>> {
+        if self.options.versions_retained > 1 {
+            return Ok(None);
+        }
+        let Some(hint) = Hint::load(&self.sidecar_path(HINT_EXTENSION)) else {
+            return Ok(None);
+        };
+
+        let log_len = fs::metadata(self.log_path())?.len();
+        if hint.generation != self.generation || hint.log_len > log_len {
+            debug!("hint is stale, replaying log");
+            return Ok(None);
+        }
+        let Some(tombstones) = hint.tombstones else {
+            debug!("hint has no tombstone count, replaying log");
+            return Ok(None);
+        };
+        if hint
+            .entries
+            .iter()
+            .any(|entry| entry.offset + entry.length > hint.log_len)
+        {
+            warn!("hint points past the log it describes, replaying log");
+            return Ok(None);
+        }
+
+        self.index.clear();
+        self.index.extend(hint.entries.into_iter().map(|entry| {
+            let cmd_pos = CommandPos {
+                offset: entry.offset,
+                length: entry.length,
+                seq: entry.seq,
+            };
+            (entry.key, cmd_pos)
+        }));
+        self.seq = hint.seq;
+        self.uncompacted = hint.uncompacted;
+        self.tombstones = tombstones;
+
+        Ok(Some(hint.log_len))
+    }
+
+    /// Discards all state rebuilt from the log so it can be replayed afresh.
+    fn reset_index(&mut self) {
+        self.index.clear();
+        self.versions.clear();
+        self.seq = 0;
+        self.uncompacted = 0;
+        self.tombstones = 0;
+        self.removed = self.options.track_removed.map(RecentlyRemoved::new);
+        if let Some(mut cache) = self.cache() {
+            cache.clear();
+        }
+    }
 
-        if !log_path.exists() {
+    /// Writes the current index to the hint file. Buffered writes must have
+    /// been flushed so the recorded log length matches the file.
+    fn save_hint(&self) -> Result<()> {
+        // Hints don't record retained versions, and are never loaded when
+        // versions are retained.
+        if self.options.versions_retained > 1 {
             return Ok(());
         }
+        let hint = Hint {
+            generation: self.generation,
+            log_len: self.current_pos,
+            seq: self.seq,
+            uncompacted: self.uncompacted,
+            tombstones: Some(self.tombstones),
+            entries: self
+                .index
+                .iter()
+                .map(|(key, cmd_pos)| HintEntry {
+                    key: key.clone(),
+                    offset: cmd_pos.offset,
+                    length: cmd_pos.length,
+                    seq: cmd_pos.seq,
+                })
+                .collect(),
+        };
+
+        hint.save(&self.sidecar_path(HINT_EXTENSION), self.options.io_retries)
+    }
+
+    /// Applies the records in the log from offset `from` onwards to the
+    /// index, returning the number of records replayed.
+    ///
+    /// `progress` is called every [`PROGRESS_INTERVAL`] bytes and at the end.
+    fn replay_log(&mut self, from: u64, progress: &mut dyn FnMut(ReplayProgress)) -> Result<u64> {
+        let log_path = self.log_path();
 
         let file = File::open(&log_path)?;
         let file_len = file.metadata()?.len();
-        let mut reader = BufReader::new(file);
-        let mut pos: u64 = 0;
+        let mut reader = BufReader::with_capacity(self.options.read_buffer_size, file);
+        let mut pos = from;
+        let mut records: u64 = 0;
+        let overhead = format::frame_overhead(self.version);
+        let mut next_progress = pos + PROGRESS_INTERVAL;
 
-        let mut previous_positions: HashMap<String, u64> = HashMap::new();
+        reader.seek(SeekFrom::Start(pos))?;
 
         while pos < file_len {
-            let mut len_buf = [0u8; 4];
-            if reader.read_exact(&mut len_buf).is_err() {
+            // A record running past the end of the log was torn by a crash or
+            // a failed write. Nothing can follow it, so it is cut off to keep
+            // new records contiguous with the rest of the log.
+            let frame = match file_len - pos {
+                remaining if remaining < overhead => None,
+                remaining => match format::read_frame(&mut reader, self.version, remaining) {
+                    Ok(frame) => Some(frame),
+                    Err(RuskError::Corruption { .. }) => None,
+                    Err(err) => return Err(err),
+                },
+            };
+            let Some(frame) = frame else {
+                warn!(
+                    "discarding truncated record at offset {} in {}",
+                    pos,
+                    log_path.display()
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(&log_path)?
+                    .set_len(pos)?;
                 break;
-            }
-            let data_len = u32::from_be_bytes(len_buf) as u64;
-
-            let mut data_buf = vec![0u8; data_len as usize];
-            reader.read_exact(&mut data_buf)?;
+            };
 
-            let cmd: Command = serde_json::from_slice(&data_buf)?;
+            // Dead bytes are derived from the index rather than tracked
+            // separately, so long runs of overwrites of one key (or a replay
+            // that starts from a hint) are accounted for exactly.
+            let record = format::decode_frame(&frame, self.version, true)?;
+            let entry_len = frame.len() as u64;
+            self.seq = self.seq.max(record.seq);
 
-            let entry_len = 4 + data_len;
-
-            match &cmd {
+            match record.command {
                 Command::Set { key, .. } => {
-                    if let Some(old_len) = previous_positions.insert(key.clone(), entry_len) {
-                        self.uncompacted += old_len;
+                    if let Some(removed) = &mut self.removed {
+                        removed.remove(&key);
                     }
-                    self.index.insert(
-                        key.clone(),
-                        CommandPos {
-                            offset: pos,
-                            length: entry_len,
-                        },
-                    );
+                    let cmd_pos = CommandPos {
+                        offset: pos,
+                        length: entry_len,
+                        seq: record.seq,
+                    };
+                    self.index_set(key, cmd_pos);
                 }
                 Command::Remove { key } => {
-                    if let Some(old_pos) = self.index.remove(key) {
-                        self.uncompacted += old_pos.length;
-                    }
+                    self.index_remove(&key);
                     self.uncompacted += entry_len;
-                    previous_positions.remove(key);
+                    self.tombstones += 1;
+                    if let Some(removed) = &mut self.removed {
+                        removed.insert(key);
+                    }
                 }
             }
 
             pos += entry_len;
+            records += 1;
+
+            if pos >= next_progress {
+                progress(ReplayProgress {
+                    bytes_read: pos,
+                    total_bytes: file_len,
+                });
+                next_progress = pos + PROGRESS_INTERVAL;
+            }
         }
+        progress(ReplayProgress {
+            bytes_read: pos,
+            total_bytes: file_len,
+        });
+
+        debug!(
+            "replayed {} records ({} bytes, {} uncompacted)",
+            records, pos, self.uncompacted
+        );
 
         self.current_pos = pos;
-        Ok(())
+        Ok(records)
     }
 
     /// Sets a key-value pair.
     ///
     /// If the key already exists, the old value is overwritten.
     pub fn set(&mut self, key: String, value: String) -> Result<()> {
-        let cmd = Command::Set {
-            key: key.clone(),
-            value,
-        };
+        self.set_at(key, value).map(|_| ())
+    }
 
-        let pos = self.write_command(&cmd)?;
+    /// Sets a key-value pair, returning the byte offset of its record in the
+    /// log.
+    ///
+    /// The offset stays valid until the log is next compacted, which moves
+    /// every record; compare [`generation`](Self::generation) to tell whether
+    /// an offset kept elsewhere is still current. If this write itself
+    /// triggers a compaction, the returned offset is already the one in the
+    /// compacted log.
+    pub fn set_at(&mut self, key: String, value: String) -> Result<u64> {
+        self.write_set(key, value, None)
+    }
 
-        if let Some(old_pos) = self.index.insert(key, pos) {
-            self.uncompacted += old_pos.length;
-        }
+    /// Sets a key-value pair along with a piece of metadata, such as a
+    /// content type, that can be read back with
+    /// [`get_meta`](Self::get_meta).
+    ///
+    /// Metadata is replaced along with the value: a later plain `set` of the
+    /// same key clears it.
+    pub fn set_with_meta(&mut self, key: String, value: String, meta: String) -> Result<()> {
+        self.write_set(key, value, Some(meta)).map(|_| ())
+    }
 
-        if self.uncompacted > COMPACTION_THRESHOLD {
-            self.compact()?;
-        }
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "rusk.set",
+            skip_all,
+            fields(key = %key, bytes = value.len(), offset = tracing::field::Empty)
+        )
+    )]
+    fn write_set(&mut self, key: String, value: String, meta: Option<String>) -> Result<u64> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let key = self.normalize_key(key);
+        self.check_key_len(&key)?;
+        self.append_set(key.clone(), value, meta, self.created_at_now())?;
+        self.flush_if_needed()?;
 
-        Ok(())
+        self.maybe_compact()?;
+        Ok(self.index[&key].offset)
     }
 
-    /// Gets the value for a key.
+    /// Adds `by` to the integer stored at `key` and returns the new value.
     ///
-    /// Returns `None` if the key doesn't exist.
-    pub fn get(&mut self, key: String) -> Result<Option<String>> {
-        if let Some(&cmd_pos) = self.index.get(&key) {
-            let log_path = self.path.join(LOG_FILE_NAME);
-            let file = File::open(&log_path)?;
-            let mut reader = BufReader::new(file);
+    /// A missing key counts as `0`. A value that doesn't parse as an `i64`
+    /// is handled according to
+    /// [`invalid_counter`](RuskStoreBuilder::invalid_counter): by default
+    /// this fails with `RuskError::NotACounter`. A result that doesn't fit in
+    /// an `i64` fails with `RuskError::CounterOverflow`. Either way nothing
+    /// is written. The new value is written like a `set`, so it clears any
+    /// metadata. Nothing else can touch the store between the read and the
+    /// write, since both happen under `&mut self`.
+    pub fn increment(&mut self, key: String, by: i64) -> Result<i64> {
+        let key = self.normalize_key(key);
+        self.check_key_len(&key)?;
 
-            reader.seek(SeekFrom::Start(cmd_pos.offset))?;
+        let current = match self.index.get(&key) {
+            Some(&cmd_pos) => match self.read_live_record(&cmd_pos)?.command {
+                Command::Set { value, .. } => match value.parse::<i64>() {
+                    Ok(current) => current,
+                    Err(_) => match self.options.invalid_counter {
+                        InvalidCounterPolicy::Error => return Err(RuskError::NotACounter { key }),
+                        InvalidCounterPolicy::Reset => 0,
+                    },
+                },
+                Command::Remove { .. } => {
+                    self.indexed_tombstone(&key, cmd_pos.offset)?;
+                    0
+                }
+            },
+            None => 0,
+        };
+        let Some(value) = current.checked_add(by) else {
+            return Err(RuskError::CounterOverflow { key });
+        };
 
-            let mut len_buf = [0u8; 4];
-            reader.read_exact(&mut len_buf)?;
-            let data_len = u32::from_be_bytes(len_buf) as usize;
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        self.append_set(key, value.to_string(), None, self.created_at_now())?;
+        self.flush_if_needed()?;
 
-            let mut data_buf = vec![0u8; data_len];
-            reader.read_exact(&mut data_buf)?;
+        self.maybe_compact()?;
+        Ok(value)
+    }
 
-            let cmd: Command = serde_json::from_slice(&data_buf)?;
-            match cmd {
-                Command::Set { value, .. } => Ok(Some(value)),
-                Command::Remove { .. } => Err(RuskError::UnexpectedCommand),
-            }
-        } else {
-            Ok(None)
+    /// Sets several key-value pairs, flushing once at the end.
+    ///
+    /// Pairs are written in order, so if a key appears more than once the
+    /// last value wins: every occurrence is written to the log, but the
+    /// index only points at the last, and the earlier ones count as dead
+    /// space for compaction exactly as if they had been separate `set`s.
+    /// Compaction is considered once after the whole batch. Every key is
+    /// checked against `max_key_len` before anything is written.
+    pub fn set_batch(&mut self, entries: impl IntoIterator<Item = (String, String)>) -> Result<()> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let entries = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let key = self.normalize_key(key);
+                self.check_key_len(&key)?;
+                self.check_encodable(&key, &value)?;
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let created_at = self.created_at_now();
+        for (key, value) in entries {
+            self.append_set(key, value, None, created_at)?;
         }
+        self.flush_if_needed()?;
+
+        self.maybe_compact()
     }
 
-    /// Removes a key from the store.
+    /// Sets the entries read from `reader` as newline-delimited JSON, one
+    /// `{"key":...,"value":...}` object per line, as written by
+    /// [`export_ndjson`](Self::export_ndjson).
     ///
-    /// Returns an error if the key doesn't exist.
-    pub fn remove(&mut self, key: String) -> Result<()> {
-        if !self.index.contains_key(&key) {
-            return Err(RuskError::KeyNotFound);
-        }
+    /// Lines are read and written one at a time, so the input is never held
+    /// in memory, and the import is flushed once at the end like a
+    /// [`set_batch`](Self::set_batch). Blank lines are ignored. A line that
+    /// isn't a valid entry, or whose key is too long or can't be encoded, is
+    /// skipped and its number recorded in the report, unless
+    /// [`import_errors`](RuskStoreBuilder::import_errors) is set to
+    /// [`ImportErrorPolicy::Abort`], in which case the import stops there
+    /// with `RuskError::InvalidImportLine`, keeping the lines before it.
+    pub fn import_ndjson(&mut self, reader: impl Read) -> Result<ImportReport> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let mut reader = BufReader::new(reader);
+        let mut report = ImportReport::default();
+        let created_at = self.created_at_now();
+
+        let mut line = Vec::new();
+        let mut line_number = 0;
+        let result = loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(err) => break Err(err.into()),
+            }
+            line_number += 1;
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
 
-        let cmd = Command::Remove { key: key.clone() };
-        let pos = self.write_command(&cmd)?;
+            let entry = serde_json::from_slice::<NdjsonEntry<String>>(&line)
+                .map_err(RuskError::from)
+                .and_then(|entry| {
+                    let key = self.normalize_key(entry.key);
+                    self.check_key_len(&key)?;
+                    self.check_encodable(&key, &entry.value)?;
+                    Ok((key, entry.value))
+                });
+            match entry {
+                Ok((key, value)) => {
+                    if let Err(err) = self.append_set(key, value, None, created_at) {
+                        break Err(err);
+                    }
+                    report.imported += 1;
+                }
+                Err(_) if self.options.import_errors == ImportErrorPolicy::Skip => {
+                    report.failed_lines.push(line_number);
+                }
+                Err(_) => break Err(RuskError::InvalidImportLine { line: line_number }),
+            }
+        };
+        self.flush_if_needed()?;
+        result?;
 
-        if let Some(old_pos) = self.index.remove(&key) {
-            self.uncompacted += old_pos.length;
+        self.maybe_compact()?;
+        Ok(report)
+    }
+
+    /// Copies every live entry of `other` into this store, resolving keys
+    /// that both have according to `conflict`.
+    ///
+    /// The entries to write are read from `other` in log order through one
+    /// reader, then written here as a single [`set_batch`](Self::set_batch)
+    /// with one flush, so they are all held in memory at once. `other` is
+    /// only read. Keys keep the form `other` stored them in, passed through
+    /// this store's key transform.
+    pub fn merge_from<T: BuildHasher + Clone>(
+        &mut self,
+        other: &mut RuskStore<T>,
+        conflict: ConflictPolicy,
+    ) -> Result<MergeReport> {
+        let mut report = MergeReport::default();
+        let mut entries: Vec<(String, CommandPos)> = Vec::new();
+        for (key, cmd_pos) in &other.index {
+            if self
+                .index
+                .contains_key(self.normalize_key_ref(key).as_ref())
+            {
+                match conflict {
+                    ConflictPolicy::Overwrite => report.overwritten += 1,
+                    ConflictPolicy::KeepExisting => {
+                        report.skipped += 1;
+                        continue;
+                    }
+                }
+            } else {
+                report.added += 1;
+            }
+            entries.push((key.clone(), *cmd_pos));
         }
-        self.uncompacted += pos.length;
+        entries.sort_unstable_by_key(|(_, cmd_pos)| cmd_pos.offset);
 
-        if self.uncompacted > COMPACTION_THRESHOLD {
-            self.compact()?;
+        let mut batch = Vec::with_capacity(entries.len());
+        for (key, cmd_pos) in entries {
+            match other.read_live_record(&cmd_pos)?.command {
+                Command::Set { value, .. } => batch.push((key, value)),
+                Command::Remove { .. } => {
+                    return Err(RuskError::IndexMismatch {
+                        key,
+                        offset: cmd_pos.offset,
+                    });
+                }
+            }
         }
+        self.set_batch(batch)?;
 
-        Ok(())
+        Ok(report)
     }
 
-    fn write_command(&mut self, cmd: &Command) -> Result<CommandPos> {
-        let data = serde_json::to_vec(cmd)?;
-        let data_len = data.len() as u32;
-
-        let offset = self.current_pos;
+    /// Returns the creation time to store with a value written now, if
+    /// creation times are being recorded.
+    fn created_at_now(&self) -> Option<u64> {
+        self.options.record_created_at.then(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64)
+        })
+    }
 
-        self.writer.write_all(&data_len.to_be_bytes())?;
-        self.writer.write_all(&data)?;
-        self.writer.flush()?;
+    /// Appends a `Set` record for `key` without flushing and updates the index.
+    fn append_set(
+        &mut self,
+        key: String,
+        value: String,
+        meta: Option<String>,
+        created_at: Option<u64>,
+    ) -> Result<()> {
+        let record = Record {
+            seq: self.next_seq(),
+            command: Command::Set {
+                key: key.clone(),
+                value,
+                meta,
+                created_at,
+            },
+        };
+        let pos = self.append_record(&record)?;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("offset", pos.offset);
+        self.record_last_write(&key, WriteKind::Set, &pos);
 
-        let entry_len = 4 + data.len() as u64;
-        self.current_pos += entry_len;
+        if let Some(removed) = &mut self.removed {
+            removed.remove(&key);
+        }
+        self.index_set(key, pos);
 
-        Ok(CommandPos {
-            offset,
-            length: entry_len,
-        })
+        Ok(())
     }
 
-    /// Compacts the log by rewriting only the live entries.
-    ///
-    /// This removes all dead space from overwritten or deleted keys.
-    pub fn compact(&mut self) -> Result<()> {
-        let compaction_path = self.path.join("data.compact");
-        let log_path = self.path.join(LOG_FILE_NAME);
+    /// Points the index at a new `Set` for `key`. The value it supersedes is
+    /// retained as an older version if `versions_retained` allows, and
+    /// otherwise counted as dead.
+    fn index_set(&mut self, key: String, cmd_pos: CommandPos) {
+        if let Some(mut cache) = self.cache() {
+            cache.remove(&key);
+        }
+        let Some(current) = self.index.get_mut(&key) else {
+            self.index.insert(key, cmd_pos);
+            return;
+        };
+        let old_pos = mem::replace(current, cmd_pos);
+
+        let retained = self.options.versions_retained - 1;
+        if retained == 0 {
+            self.uncompacted += old_pos.length;
+            return;
+        }
+        let versions = match self.versions.get_mut(&key) {
+            Some(versions) => versions,
+            None => self.versions.entry(key).or_default(),
+        };
+        versions.push_back(old_pos);
+        if versions.len() > retained
+            && let Some(dropped) = versions.pop_front()
+        {
+            self.uncompacted += dropped.length;
+        }
+    }
+
+    /// Drops `key` and any versions retained for it from the index, counting
+    /// them all as dead.
+    fn index_remove(&mut self, key: &str) {
+        if let Some(mut cache) = self.cache() {
+            cache.remove(key);
+        }
+        if let Some(old_pos) = self.index.remove(key) {
+            self.uncompacted += old_pos.length;
+        }
+        if let Some(versions) = self.versions.remove(key) {
+            self.uncompacted += versions.iter().map(|pos| pos.length).sum::<u64>();
+        }
+    }
+
+    /// Handles an index entry for `key` that points at a tombstone, which
+    /// only happens if the index and the log have diverged.
+    ///
+    /// With [`strict_reads`](RuskStoreBuilder::strict_reads) this fails with
+    /// `RuskError::IndexMismatch`. Otherwise the bogus entry is dropped, so
+    /// the key reads as missing from then on.
+    fn indexed_tombstone(&mut self, key: &str, offset: u64) -> Result<()> {
+        if self.options.strict_reads {
+            return Err(RuskError::IndexMismatch {
+                key: key.to_owned(),
+                offset,
+            });
+        }
+        warn!(
+            "index entry for {} points at a tombstone at offset {}, dropping it",
+            key, offset
+        );
+        self.index_remove(key);
+        Ok(())
+    }
+
+    /// Gets the value for a key.
+    ///
+    /// Returns `None` if the key doesn't exist.
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        if let Some(value) = self.get_cached(&key) {
+            return Ok(Some(value));
+        }
+        Ok(self.get_with_metadata(key)?.map(|entry| entry.value))
+    }
+
+    /// Gets the value for a key from the value cache, without touching the
+    /// log.
+    ///
+    /// Returns `None` on a cache miss as well as for keys that don't exist;
+    /// fall back to [`get`](Self::get), which reads the log and caches what
+    /// it reads. Since this only borrows the store shared, readers sharing
+    /// it (e.g. behind an `RwLock`) can be served from the cache
+    /// concurrently. Always `None` unless
+    /// [`value_cache`](RuskStoreBuilder::value_cache) is enabled.
+    pub fn get_cached(&self, key: &str) -> Option<String> {
+        let mut cache = self.cache()?;
+        cache.get(&self.normalize_key_ref(key))
+    }
+
+    /// Gets the value for a key together with how long ago it was written.
+    ///
+    /// The age is `None` if the value has no creation time, i.e. it was
+    /// written without [`record_created_at`] enabled. A creation time in the
+    /// future (after the clock was set back) reads as an age of zero.
+    ///
+    /// [`record_created_at`]: RuskStoreBuilder::record_created_at
+    pub fn get_with_age(&mut self, key: String) -> Result<Option<(String, Option<Duration>)>> {
+        let key = self.normalize_key(key);
+        let Some(&cmd_pos) = self.index.get(&key) else {
+            return Ok(None);
+        };
+
+        match self.read_live_record(&cmd_pos)?.command {
+            Command::Set {
+                value, created_at, ..
+            } => {
+                let age = created_at.map(|created_at| {
+                    let created = UNIX_EPOCH + Duration::from_millis(created_at);
+                    SystemTime::now()
+                        .duration_since(created)
+                        .unwrap_or(Duration::ZERO)
+                });
+                Ok(Some((value, age)))
+            }
+            Command::Remove { .. } => {
+                self.indexed_tombstone(&key, cmd_pos.offset)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Gets an older value of a key kept by
+    /// [`versions_retained`](RuskStoreBuilder::versions_retained).
+    ///
+    /// `n` counts back from the current value, so `0` is the same as
+    /// [`get`](Self::get), `1` the value it replaced, and so on. Returns
+    /// `None` if the key doesn't exist or fewer than `n + 1` of its versions
+    /// are retained. Versions from before the key was last removed are never
+    /// returned.
+    pub fn get_version(&mut self, key: String, n: usize) -> Result<Option<String>> {
+        let key = self.normalize_key(key);
+        let cmd_pos = match n {
+            0 => self.index.get(&key),
+            n => self
+                .versions
+                .get(&key)
+                .and_then(|versions| versions.iter().rev().nth(n - 1)),
+        };
+        let Some(&cmd_pos) = cmd_pos else {
+            return Ok(None);
+        };
+
+        match self.read_live_record(&cmd_pos)?.command {
+            Command::Set { value, .. } => Ok(Some(value)),
+            Command::Remove { .. } if n == 0 => {
+                self.indexed_tombstone(&key, cmd_pos.offset)?;
+                Ok(None)
+            }
+            Command::Remove { .. } => Err(RuskError::IndexMismatch {
+                key,
+                offset: cmd_pos.offset,
+            }),
+        }
+    }
+
+    /// Opens a reader over the value of `key`, or returns `None` if the key
+    /// doesn't exist.
+    ///
+    /// The reader owns its own handle to the log, so it stays valid while the
+    /// store is used (or even compacted) and yields exactly the value's
+    /// bytes. In binary-encoded logs ([`Encoding::Binary`]) the value is
+    /// streamed from disk without being loaded into memory. JSON-encoded
+    /// values are escaped on disk, so for those the record is decoded and
+    /// the reader serves the decoded value from memory.
+    ///
+    /// [`Encoding::Binary`]: crate::Encoding::Binary
+    pub fn open_value(&self, key: &str) -> Result<Option<impl Read + use<S>>> {
+        let key = self.normalize_key_ref(key);
+        let Some(&cmd_pos) = self.index.get(key.as_ref()) else {
+            return Ok(None);
+        };
+
+        self.flush_pending()?;
+        let mut file = File::open(self.log_path())?;
+
+        if self.version == format::BINARY_VERSION {
+            file.seek(SeekFrom::Start(cmd_pos.offset))?;
+            let value_len =
+                format::seek_binary_value(&mut file, cmd_pos.length, self.options.verify_on_read)?;
+            return Ok(Some(ValueReader::Stream(file.take(value_len))));
+        }
+
+        let mut reader = BufReader::with_capacity(self.options.read_buffer_size, file);
+        match self.read_record_at(&mut reader, &cmd_pos)?.command {
+            Command::Set { value, .. } => {
+                Ok(Some(ValueReader::Buffered(Cursor::new(value.into_bytes()))))
+            }
+            Command::Remove { .. } => Err(RuskError::IndexMismatch {
+                key: key.into_owned(),
+                offset: cmd_pos.offset,
+            }),
+        }
+    }
+
+    /// Gets the metadata stored with a key by
+    /// [`set_with_meta`](Self::set_with_meta).
+    ///
+    /// Returns `None` if the key doesn't exist or was set without metadata.
+    pub fn get_meta(&mut self, key: String) -> Result<Option<String>> {
+        let key = self.normalize_key(key);
+        let Some(&cmd_pos) = self.index.get(&key) else {
+            return Ok(None);
+        };
+
+        match self.read_live_record(&cmd_pos)?.command {
+            Command::Set { meta, .. } => Ok(meta),
+            Command::Remove { .. } => {
+                self.indexed_tombstone(&key, cmd_pos.offset)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Gets the value for a key along with its offset, length and sequence
+    /// number in the log.
+    ///
+    /// Returns `None` if the key doesn't exist.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "rusk.get",
+            skip_all,
+            fields(key = %key, bytes = tracing::field::Empty, offset = tracing::field::Empty)
+        )
+    )]
+    pub fn get_with_metadata(&mut self, key: String) -> Result<Option<Entry>> {
+        let key = self.normalize_key(key);
+        if let Some(&cmd_pos) = self.index.get(&key) {
+            #[cfg(feature = "tracing")]
+            tracing::Span::current()
+                .record("bytes", cmd_pos.length)
+                .record("offset", cmd_pos.offset);
+            let record = self.read_live_record(&cmd_pos)?;
+            match record.command {
+                Command::Set { value, .. } => {
+                    if let Some(mut cache) = self.cache() {
+                        cache.insert(key, value.clone());
+                    }
+                    Ok(Some(Entry {
+                        value,
+                        offset: cmd_pos.offset,
+                        length: cmd_pos.length,
+                        seq: record.seq,
+                    }))
+                }
+                Command::Remove { .. } => {
+                    self.indexed_tombstone(&key, cmd_pos.offset)?;
+                    Ok(None)
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Gets the values of several keys, returning only the keys that exist.
+    ///
+    /// All keys are read through the same reader, and each distinct key is
+    /// read at most once. Keys in the returned map are in their stored form,
+    /// i.e. after any configured key transform has been applied.
+    pub fn get_map(&mut self, keys: &[String]) -> Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        for key in keys {
+            let key = self.normalize_key_ref(key);
+            if values.contains_key(key.as_ref()) {
+                continue;
+            }
+            let Some(&cmd_pos) = self.index.get(key.as_ref()) else {
+                continue;
+            };
+
+            match self.read_live_record(&cmd_pos)?.command {
+                Command::Set { value, .. } => {
+                    values.insert(key.into_owned(), value);
+                }
+                Command::Remove { .. } => self.indexed_tombstone(&key, cmd_pos.offset)?,
+            }
+        }
+        Ok(values)
+    }
+
+    /// Returns up to `limit` key-value pairs with keys `>= start`, in
+    /// ascending key order.
+    ///
+    /// `start` goes through the key transform like any other key. Only the
+    /// returned keys' values are read, all through the same reader. As with
+    /// [`keys_page`](Self::keys_page), the index isn't ordered, so finding
+    /// the range costs a pass over all keys.
+    pub fn range_values(&mut self, start: &str, limit: usize) -> Result<Vec<(String, String)>> {
+        let start = self.normalize_key_ref(start);
+        let mut range: Vec<(&str, CommandPos)> = self
+            .index
+            .iter()
+            .filter(|(key, _)| key.as_str() >= start.as_ref())
+            .map(|(key, cmd_pos)| (key.as_str(), *cmd_pos))
+            .collect();
+        if range.len() > limit {
+            range.select_nth_unstable_by_key(limit, |(key, _)| *key);
+            range.truncate(limit);
+        }
+        range.sort_unstable_by_key(|(key, _)| *key);
+        let range: Vec<(String, CommandPos)> = range
+            .into_iter()
+            .map(|(key, cmd_pos)| (key.to_owned(), cmd_pos))
+            .collect();
+
+        let mut values = Vec::with_capacity(range.len());
+        for (key, cmd_pos) in range {
+            match self.read_live_record(&cmd_pos)?.command {
+                Command::Set { value, .. } => values.push((key, value)),
+                Command::Remove { .. } => self.indexed_tombstone(&key, cmd_pos.offset)?,
+            }
+        }
+        Ok(values)
+    }
+
+    /// Writes every live entry to `writer` as newline-delimited JSON, one
+    /// `{"key":...,"value":...}` object per line, and returns how many were
+    /// written.
+    ///
+    /// Entries are read in log order and written as they are read, so only
+    /// one value is held in memory at a time. Keys and values are escaped
+    /// as JSON strings. Lines are in no particular key order.
+    pub fn export_ndjson(&mut self, writer: impl Write) -> Result<usize> {
+        let mut entries: Vec<_> = self.index.iter().collect();
+        entries.sort_unstable_by_key(|(_, cmd_pos)| cmd_pos.offset);
+
+        let mut reader = self.open_reader()?;
+        let mut writer = BufWriter::new(writer);
+        for (key, cmd_pos) in &entries {
+            match self.read_record_at(&mut reader, cmd_pos)?.command {
+                Command::Set { value, .. } => {
+                    let entry = NdjsonEntry {
+                        key: key.as_str(),
+                        value: value.as_str(),
+                    };
+                    serde_json::to_writer(&mut writer, &entry)?;
+                    writer.write_all(b"\n")?;
+                }
+                Command::Remove { .. } => {
+                    return Err(RuskError::IndexMismatch {
+                        key: (*key).clone(),
+                        offset: cmd_pos.offset,
+                    });
+                }
+            }
+        }
+        writer.flush()?;
+
+        Ok(entries.len())
+    }
+
+    /// Checks that every index entry points at an intact `Set` record for its
+    /// key, with the length and sequence number the index recorded.
+    ///
+    /// Each indexed record is read once, in log order. Returns
+    /// `RuskError::IndexMismatch` for the first entry that doesn't match;
+    /// IO errors are returned as-is.
+    pub fn verify_index(&mut self) -> Result<()> {
+        let mut entries: Vec<_> = self.index.iter().collect();
+        entries.sort_unstable_by_key(|(_, cmd_pos)| cmd_pos.offset);
+
+        let mut reader = self.open_reader()?;
+        for (key, cmd_pos) in entries {
+            let mismatch = || RuskError::IndexMismatch {
+                key: key.clone(),
+                offset: cmd_pos.offset,
+            };
+
+            reader.seek(SeekFrom::Start(cmd_pos.offset))?;
+            let frame = match format::read_frame(&mut reader, self.version, cmd_pos.length) {
+                Ok(frame) => frame,
+                Err(RuskError::Io(err)) if err.kind() != io::ErrorKind::UnexpectedEof => {
+                    return Err(err.into());
+                }
+                Err(_) => return Err(mismatch()),
+            };
+            if frame.len() as u64 != cmd_pos.length {
+                return Err(mismatch());
+            }
+
+            match format::decode_frame(&frame, self.version, true) {
+                Ok(Record {
+                    seq,
+                    command: Command::Set { key: found, .. },
+                }) if found == *key && seq == cmd_pos.seq => {}
+                _ => return Err(mismatch()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Buckets the live entries by size.
+    ///
+    /// By default this uses the record lengths in the index, which include
+    /// the key and framing, and never touches disk. With `precise` set, it
+    /// reads every value from the log and buckets the value lengths instead.
+    pub fn size_histogram(&mut self, precise: bool) -> Result<SizeHistogram> {
+        let mut histogram = SizeHistogram::default();
+        if !precise {
+            for cmd_pos in self.index.values() {
+                histogram.add(cmd_pos.length);
+            }
+            return Ok(histogram);
+        }
+
+        let mut reader = self.open_reader()?;
+        for (key, cmd_pos) in &self.index {
+            match self.read_record_at(&mut reader, cmd_pos)?.command {
+                Command::Set { value, .. } => histogram.add(value.len() as u64),
+                Command::Remove { .. } => {
+                    return Err(RuskError::IndexMismatch {
+                        key: key.clone(),
+                        offset: cmd_pos.offset,
+                    });
+                }
+            }
+        }
+        Ok(histogram)
+    }
+
+    /// Reports whether `key` is present, recently removed, or unknown.
+    ///
+    /// Telling removed keys apart requires [`RuskStoreBuilder::track_removed`];
+    /// without it, absent keys are always reported as `Unknown`. This only
+    /// consults memory and never reads the log.
+    pub fn get_status(&self, key: &str) -> KeyStatus {
+        let key = self.normalize_key_ref(key);
+        if self.index.contains_key(key.as_ref()) {
+            KeyStatus::Present
+        } else if self
+            .removed
+            .as_ref()
+            .is_some_and(|removed| removed.contains(&key))
+        {
+            KeyStatus::Removed
+        } else {
+            KeyStatus::Unknown
+        }
+    }
+
+    /// Removes a key from the store.
+    ///
+    /// Returns `RuskError::KeyNotFound` if the key doesn't exist.
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let key = self.normalize_key(key);
+        self.check_key_len(&key)?;
+        if !self.index.contains_key(&key) {
+            return Err(RuskError::KeyNotFound(key));
+        }
+
+        self.append_remove(key)?;
+        self.flush_if_needed()?;
+
+        self.maybe_compact()
+    }
+
+    /// Removes every key for which `f` returns `false`, returning the number
+    /// of keys removed.
+    ///
+    /// Tombstones are written as a single batch with one flush, and compaction
+    /// is considered once at the end rather than after each removal.
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut f: F) -> Result<usize> {
+        let doomed: Vec<String> = self.index.keys().filter(|key| !f(key)).cloned().collect();
+        self.remove_all(doomed)
+    }
+
+    /// Like [`RuskStore::retain`], but the predicate also receives each value.
+    ///
+    /// This reads every live value from disk.
+    pub fn retain_with_value<F: FnMut(&str, &str) -> bool>(&mut self, mut f: F) -> Result<usize> {
+        let mut reader = self.open_reader()?;
+        let mut doomed = Vec::new();
+
+        for (key, cmd_pos) in &self.index {
+            match self.read_record_at(&mut reader, cmd_pos)?.command {
+                Command::Set { value, .. } => {
+                    if !f(key, &value) {
+                        doomed.push(key.clone());
+                    }
+                }
+                Command::Remove { .. } => {
+                    return Err(RuskError::IndexMismatch {
+                        key: key.clone(),
+                        offset: cmd_pos.offset,
+                    });
+                }
+            }
+        }
+
+        self.remove_all(doomed)
+    }
+
+    /// Moves the value (and any metadata) of `from` to `to`, overwriting any
+    /// existing value of `to`.
+    ///
+    /// The new `Set` is written before `from` is tombstoned and both are
+    /// flushed together, so a crash part-way through can at worst leave the
+    /// value under both keys, never under neither. Returns
+    /// `RuskError::KeyNotFound` if `from` doesn't exist.
+    pub fn rename(&mut self, from: String, to: String) -> Result<()> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let from = self.normalize_key(from);
+        let to = self.normalize_key(to);
+        self.check_key_len(&from)?;
+        self.check_key_len(&to)?;
+        let Some(&cmd_pos) = self.index.get(&from) else {
+            return Err(RuskError::KeyNotFound(from));
+        };
+        if from == to {
+            return Ok(());
+        }
+
+        let mut reader = self.open_reader()?;
+        let (value, meta, created_at) = match self.read_record_at(&mut reader, &cmd_pos)?.command {
+            Command::Set {
+                value,
+                meta,
+                created_at,
+                ..
+            } => (value, meta, created_at),
+            Command::Remove { .. } => {
+                return Err(RuskError::IndexMismatch {
+                    key: from,
+                    offset: cmd_pos.offset,
+                });
+            }
+        };
+        drop(reader);
+
+        self.append_set(to, value, meta, created_at)?;
+        self.append_remove(from)?;
+        self.flush_if_needed()?;
+
+        self.maybe_compact()
+    }
+
+    /// Removes several keys, flushing once at the end.
+    ///
+    /// Returns whether each key was present. Keys that are absent are skipped
+    /// rather than treated as an error, so a key repeated in `keys` reports
+    /// `false` for every occurrence after the first. Compaction is considered
+    /// once after the whole batch, and every key is checked against
+    /// `max_key_len` before anything is written.
+    pub fn remove_batch(&mut self, keys: &[String]) -> Result<Vec<bool>> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let key = self.normalize_key(key.clone());
+                self.check_key_len(&key)?;
+                Ok(key)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut present = Vec::with_capacity(keys.len());
+        for key in keys {
+            if self.index.contains_key(&key) {
+                self.append_remove(key)?;
+                present.push(true);
+            } else {
+                present.push(false);
+            }
+        }
+        self.flush_if_needed()?;
+
+        self.maybe_compact()?;
+        Ok(present)
+    }
+
+    fn remove_all(&mut self, keys: Vec<String>) -> Result<usize> {
+        self.last_auto_compaction = AutoCompaction::NotAttempted;
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let removed = keys.len();
+        for key in keys {
+            self.append_remove(key)?;
+        }
+        self.flush_if_needed()?;
+
+        self.maybe_compact()?;
+        Ok(removed)
+    }
+
+    /// Appends a tombstone for `key` without flushing and drops it from the index.
+    fn append_remove(&mut self, key: String) -> Result<()> {
+        let record = Record {
+            seq: self.next_seq(),
+            command: Command::Remove { key: key.clone() },
+        };
+        let pos = self.append_record(&record)?;
+        self.record_last_write(&key, WriteKind::Remove, &pos);
+
+        self.index_remove(&key);
+        self.uncompacted += pos.length;
+        self.tombstones += 1;
+
+        if let Some(removed) = &mut self.removed {
+            removed.insert(key);
+        }
+
+        Ok(())
+    }
+
+    fn record_last_write(&mut self, key: &str, kind: WriteKind, pos: &CommandPos) {
+        self.last_write = Some(LastWrite {
+            key: key.to_owned(),
+            kind,
+            offset: pos.offset,
+            seq: pos.seq,
+            generation: self.generation,
+        });
+    }
+
+    pub(crate) fn maybe_compact(&mut self) -> Result<()> {
+        if self.uncompacted > self.options.compaction_threshold {
+            debug!(
+                "{} uncompacted bytes exceed threshold, compacting",
+                self.uncompacted
+            );
+            self.auto_compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Compacts on behalf of a write, recording the outcome for
+    /// [`last_auto_compaction`](Self::last_auto_compaction). A compaction
+    /// already running elsewhere is not an error.
+    fn auto_compact(&mut self) -> Result<()> {
+        let result = self.compact();
+        self.last_auto_compaction = match &result {
+            Ok(_) => AutoCompaction::Completed,
+            Err(RuskError::CompactionInProgress) => AutoCompaction::Skipped,
+            Err(_) => AutoCompaction::Failed,
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(RuskError::CompactionInProgress) => {
+                debug!("skipping compaction, another compaction is in progress");
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reports whether the most recent write operation (`set`, `remove`,
+    /// their batch forms, `retain` or `rename`) compacted the log, either
+    /// because it crossed the compaction threshold or to stay within
+    /// `max_log_size`.
+    pub fn last_auto_compaction(&self) -> AutoCompaction {
+        self.last_auto_compaction
+    }
+
+    /// Returns the most recent record appended through this handle, or
+    /// `None` if nothing has been written since the store was opened.
+    ///
+    /// Compaction isn't a write and leaves this unchanged, so after one the
+    /// reported `offset` refers to the previous log generation.
+    pub fn last_write(&self) -> Option<LastWrite> {
+        self.last_write.clone()
+    }
+
+    /// Returns counters of the bytes written through this handle since the
+    /// store was opened, including by compaction.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    /// Returns the value cache's hit, miss and eviction counts since the
+    /// store was opened or [`reset_cache_stats`](Self::reset_cache_stats)
+    /// was last called, along with its current size.
+    ///
+    /// Without a [`value_cache`](RuskStoreBuilder::value_cache) the counts
+    /// are all zero and `capacity` is `None`.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache().map(|cache| cache.stats()).unwrap_or_default()
+    }
+
+    /// Zeroes the hit, miss and eviction counts reported by
+    /// [`cache_stats`](Self::cache_stats). Cached values are kept.
+    pub fn reset_cache_stats(&self) {
+        if let Some(mut cache) = self.cache() {
+            cache.reset_stats();
+        }
+    }
+
+    /// Flushes any buffered writes to the log file.
+    ///
+    /// This is only needed in [`FlushMode::Manual`] and
+    /// [`FlushMode::Batched`]; the default mode flushes after every write. In
+    /// [`DurabilityMode::Fsync`] the log is also synced to disk.
+    ///
+    /// If the disk is full, the buffered writes are discarded and the store
+    /// rolled back to what is on disk, and `RuskError::DiskFull` is
+    /// returned. The store stays usable, and writes succeed again once space
+    /// has been freed.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_log(self.options.durability == DurabilityMode::Fsync)?;
+
+        if self.options.durability == DurabilityMode::Fsync
+            && let Some(file) = self.mirror.as_ref().and_then(|mirror| mirror.file.as_ref())
+            && let Err(err) = file.sync_data()
+        {
+            self.mirror_failed(err)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes buffered writes to the log, syncing it to disk if `sync` is
+    /// set, with the same retries and disk-full rollback as appending.
+    fn flush_log(&mut self, sync: bool) -> Result<()> {
+        let result = {
+            let mut writer = self.writer();
+            let retries = self.options.io_retries;
+            retry_io(retries, || writer.flush()).and_then(|()| {
+                if sync {
+                    retry_io(retries, || writer.get_ref().sync_data())?;
+                }
+                Ok(())
+            })
+        };
+        self.check_disk_full(result)?;
+        self.pending_writes = 0;
+        Ok(())
+    }
+
+    /// Passes `result` through, first rolling the store back to the log on
+    /// disk if it failed because the disk is full.
+    fn check_disk_full<T>(&mut self, result: io::Result<T>) -> Result<T> {
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::StorageFull => {
+                if let Err(err) = self.recover_from_disk_full() {
+                    warn!("failed to roll back after running out of disk: {}", err);
+                }
+                Err(RuskError::DiskFull)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Discards buffered writes and rebuilds the index from the log.
+    ///
+    /// A write that ran out of space may have left part of a record at the
+    /// end of the log; replay cuts it off, so the in-memory state matches
+    /// exactly the records that made it to disk.
+    fn recover_from_disk_full(&mut self) -> Result<()> {
+        warn!("disk full, rolling back to the log on disk");
+        let file = OpenOptions::new().append(true).open(self.log_path())?;
+        let discarded = mem::replace(
+            &mut *self.writer(),
+            BufWriter::with_capacity(self.options.write_buffer_size, file),
+        );
+        // Dropping the old writer would try to flush its buffer again.
+        let _ = discarded.into_parts();
+        self.pending_writes = 0;
+
+        self.reset_index();
+        self.replay_log(format::data_start(self.version), &mut |_| {})?;
+        // The mirror may have received the records that were just lost.
+        if let Some(mirror) = &mut self.mirror {
+            mirror.file = None;
+        }
+        if self
+            .last_write
+            .as_ref()
+            .is_some_and(|write| write.offset >= self.current_pos)
+        {
+            self.last_write = None;
+        }
+        Ok(())
+    }
+
+    /// Closes the store, returning any error from the final flush.
+    ///
+    /// Dropping a store does the same, but can only log failures. This stops
+    /// the background flusher, flushes buffered writes (syncing them in
+    /// [`DurabilityMode::Fsync`]) and writes the index cache if enabled. The
+    /// store is consumed either way; if this fails, nothing is retried on
+    /// drop.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if let Some(flusher) = self.flusher.take() {
+            flusher.stop();
+        }
+
+        self.flush()?;
+        if self.options.index_cache {
+            self.save_hint()?;
+        }
+        Ok(())
+    }
+
+    /// Records a recovery point: flushes and syncs the log regardless of the
+    /// durability mode, then writes the index to the hint file.
+    ///
+    /// Unlike compaction this doesn't reclaim any space. If the process
+    /// crashes afterwards, the next open loads the index from the hint and
+    /// only replays records written after the checkpoint.
+    ///
+    /// Transient IO errors are retried as for any other write (see
+    /// [`io_retries`](RuskStoreBuilder::io_retries)), and a full disk is
+    /// handled as in [`flush`](Self::flush).
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.flush_log(true)?;
+        self.save_hint()
+    }
+
+    fn flush_if_needed(&mut self) -> Result<()> {
+        match self.options.flush_mode {
+            FlushMode::EveryWrite => self.flush(),
+            FlushMode::Batched(policy) if self.pending_writes >= policy.max_writes => self.flush(),
+            FlushMode::Manual | FlushMode::Batched(_) => Ok(()),
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.path.join(&self.options.log_file_name)
+    }
+
+    /// Returns the path of a file kept alongside the log, named after it
+    /// with the given extension, e.g. `data.hint` for `data.log`.
+    fn sidecar_path(&self, extension: &str) -> PathBuf {
+        let name = Path::new(&self.options.log_file_name).with_extension(extension);
+        self.path.join(name)
+    }
+
+    fn writer(&self) -> MutexGuard<'_, BufWriter<File>> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn cache(&self) -> Option<MutexGuard<'_, ValueCache>> {
+        let cache = self.cache.as_ref()?;
+        Some(cache.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Opens a reader over the log, first flushing any buffered writes so
+    /// they are visible to it.
+    fn open_reader(&self) -> Result<BufReader<File>> {
+        self.flush_pending()?;
+
+        let file = File::open(self.log_path())?;
+        Ok(BufReader::with_capacity(
+            self.options.read_buffer_size,
+            file,
+        ))
+    }
+
+    /// Flushes buffered writes, if any, so readers can see them.
+    fn flush_pending(&self) -> Result<()> {
+        let mut writer = self.writer();
+        if !writer.buffer().is_empty() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the record an index entry points at through the cached reader,
+    /// reopening it first if the log has been compacted since it was opened.
+    ///
+    /// Compaction replaces the log file, so a reader opened before it would
+    /// still see the old file; checking the generation on every read is what
+    /// makes keeping a reader around safe.
+    ///
+    /// An entry pointing past the end of the log is reported as
+    /// `RuskError::Corruption` with the entry's offset rather than an
+    /// unexpected EOF. Entries past the end the store wrote itself are
+    /// caught before reading; the file's length, in case the log was
+    /// truncated behind the store's back, is only checked once a read has
+    /// failed, so successful reads cost nothing extra.
+    fn read_live_record(&mut self, cmd_pos: &CommandPos) -> Result<Record> {
+        let past_end = |len: u64| {
+            cmd_pos
+                .offset
+                .checked_add(cmd_pos.length)
+                .is_none_or(|end| end > len)
+        };
+        let corruption = || RuskError::Corruption {
+            offset: Some(cmd_pos.offset),
+        };
+        if past_end(self.current_pos) {
+            return Err(corruption());
+        }
+
+        self.flush_pending()?;
+
+        let generation = self.generation;
+        let reader = match &mut self.cached_reader {
+            Some(cached) if cached.generation == generation => &mut cached.reader,
+            cached => {
+                let file = File::open(self.path.join(&self.options.log_file_name))?;
+                let reader = BufReader::with_capacity(self.options.read_buffer_size, file);
+                &mut cached.insert(CachedReader { generation, reader }).reader
+            }
+        };
+
+        let result = read_record_at(reader, cmd_pos, self.version, self.options.verify_on_read);
+        if result.is_err() && past_end(reader.get_ref().metadata()?.len()) {
+            return Err(corruption());
+        }
+        result
+    }
+
+    /// Reads and decodes the record stored at `offset`, verifying its
+    /// checksum if `verify_on_read` is enabled.
+    fn read_record_at(&self, reader: &mut BufReader<File>, cmd_pos: &CommandPos) -> Result<Record> {
+        read_record_at(reader, cmd_pos, self.version, self.options.verify_on_read)
+    }
+
+    /// Reads the command recorded at `offset`, e.g. one returned by
+    /// [`set_at`](Self::set_at) or [`tail`](Self::tail).
+    ///
+    /// The record's checksum is always verified. An offset that isn't the
+    /// start of a record in the current log (including one from before the
+    /// last compaction that no longer lines up) returns
+    /// `RuskError::Corruption`. Plaintext records have no checksum, so there
+    /// the offset must instead start a line.
+    pub fn read_at(&self, offset: u64) -> Result<Command> {
+        let overhead = format::frame_overhead(self.version);
+        if offset < format::data_start(self.version)
+            || offset
+                .checked_add(overhead)
+                .is_none_or(|end| end > self.current_pos)
+        {
+            return Err(RuskError::Corruption {
+                offset: Some(offset),
+            });
+        }
+
+        let mut reader = self.open_reader()?;
+        self.check_line_start(&mut reader, offset)?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let result = format::read_frame(&mut reader, self.version, self.current_pos - offset)
+            .and_then(|frame| format::decode_frame(&frame, self.version, true));
+        match result {
+            Ok(record) => Ok(record.command),
+            Err(
+                RuskError::Serde(_) | RuskError::ChecksumMismatch | RuskError::Corruption { .. },
+            ) => Err(RuskError::Corruption {
+                offset: Some(offset),
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// In a plaintext log, returns `RuskError::Corruption` unless `offset` is
+    /// the start of a line. Other encodings detect a misaligned offset by its
+    /// checksum instead, so they aren't checked here.
+    fn check_line_start(&self, reader: &mut BufReader<File>, offset: u64) -> Result<()> {
+        if self.version != format::PLAINTEXT_VERSION || offset == format::data_start(self.version) {
+            return Ok(());
+        }
+
+        // Plaintext keys and values can't contain newlines, so a record
+        // starts exactly where a newline ends the previous one.
+        let mut previous = [0u8; 1];
+        reader.seek(SeekFrom::Start(offset - 1))?;
+        reader.read_exact(&mut previous)?;
+        if previous[0] != b'\n' {
+            return Err(RuskError::Corruption {
+                offset: Some(offset),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns a read-only snapshot of the store as it is now.
+    ///
+    /// The snapshot copies the index, so creating one is O(n) in the number
+    /// of keys; cloning the returned reader is cheap. See [`RuskReader`].
+    pub fn reader(&self) -> Result<RuskReader> {
+        Ok(RuskReader::new(
+            Box::new(self.open_reader()?),
+            self.index
+                .iter()
+                .map(|(key, cmd_pos)| (key.clone(), *cmd_pos))
+                .collect(),
+            self.version,
+            self.options.verify_on_read,
+            self.options.key_transform,
+        ))
+    }
+
+    /// Returns an iterator over the commands in the log from `from_offset` to
+    /// the end of the log, yielding each command with its byte offset.
+    ///
+    /// This exposes the log as an event stream, e.g. for change data capture.
+    /// `from_offset` must be a record boundary, such as `0` (the start of the
+    /// log) or an offset previously yielded by this iterator. Records appended
+    /// after the iterator is created are not included. In a plaintext log, an
+    /// offset that doesn't start a line returns `RuskError::Corruption`.
+    pub fn tail(&self, from_offset: u64) -> Result<impl Iterator<Item = Result<(u64, Command)>>> {
+        let from_offset = from_offset.max(format::data_start(self.version));
+        let mut reader = self.open_reader()?;
+        let end = reader.get_ref().metadata()?.len();
+        if from_offset < end {
+            self.check_line_start(&mut reader, from_offset)?;
+        }
+        reader.seek(SeekFrom::Start(from_offset))?;
+
+        Ok(Tail {
+            reader,
+            pos: from_offset,
+            end,
+            version: self.version,
+        })
+    }
+
+    /// Returns every `Set` and `Remove` for `key` still in the log, oldest
+    /// first.
+    ///
+    /// This scans the whole log rather than consulting the index, so it
+    /// includes superseded values that compaction hasn't reclaimed yet. Keys
+    /// that were never written (or whose records were all compacted away)
+    /// return an empty `Vec`.
+    pub fn history(&self, key: &str) -> Result<Vec<HistoryEntry>> {
+        let key = self.normalize_key_ref(key);
+        let mut reader = self.open_reader()?;
+        let end = reader.get_ref().metadata()?.len();
+        let mut pos = format::data_start(self.version);
+        reader.seek(SeekFrom::Start(pos))?;
+
+        let mut history = Vec::new();
+        while pos < end {
+            let (record, length) = format::read_record(&mut reader, self.version)?;
+            if record.command.key() == key {
+                history.push(HistoryEntry {
+                    offset: pos,
+                    seq: record.seq,
+                    command: record.command,
+                });
+            }
+            pos += length;
+        }
+
+        Ok(history)
+    }
+
+    /// Returns the keys set or removed by writes with a sequence number
+    /// greater than `seq`, with the kind of each key's latest write, in the
+    /// order of those writes.
+    ///
+    /// This scans the whole log, so it sees removals as well as sets.
+    /// Compaction drops superseded records and tombstones, so once the log
+    /// has been compacted, changes up to the sequence number it had reached
+    /// can no longer be listed; asking for an earlier `seq` returns
+    /// `RuskError::SeqCompacted`. Records without sequence numbers, i.e.
+    /// written by older versions or in the plaintext encoding, are never
+    /// reported.
+    pub fn keys_modified_since(&self, seq: u64) -> Result<Vec<(String, WriteKind)>> {
+        if seq < self.compacted_seq {
+            return Err(RuskError::SeqCompacted {
+                seq,
+                compacted: self.compacted_seq,
+            });
+        }
+        if seq >= self.seq {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = self.open_reader()?;
+        let end = reader.get_ref().metadata()?.len();
+        let mut pos = format::data_start(self.version);
+        reader.seek(SeekFrom::Start(pos))?;
+
+        let mut changes = HashMap::new();
+        while pos < end {
+            let (record, length) = format::read_record(&mut reader, self.version)?;
+            if record.seq > seq {
+                let (key, kind) = match record.command {
+                    Command::Set { key, .. } => (key, WriteKind::Set),
+                    Command::Remove { key } => (key, WriteKind::Remove),
+                };
+                changes.insert(key, (record.seq, kind));
+            }
+            pos += length;
+        }
+
+        let mut changes: Vec<_> = changes.into_iter().collect();
+        changes.sort_unstable_by_key(|(_, (seq, _))| *seq);
+        Ok(changes
+            .into_iter()
+            .map(|(key, (_, kind))| (key, kind))
+            .collect())
+    }
+
+    /// Returns an iterator over all live keys, in no particular order.
+    ///
+    /// Keys are yielded in the form they are stored, i.e. after any
+    /// configured key transform has been applied.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    /// Returns up to `limit` live keys greater than `after`, in sorted order.
+    ///
+    /// Pass `None` for the first page and the last key of each page as
+    /// `after` for the next; a page shorter than `limit` is the last. Keys
+    /// added or removed between calls are picked up or skipped according to
+    /// where they sort, but no key that exists throughout is ever returned
+    /// twice or missed. The index isn't ordered, so each page costs a pass
+    /// over all keys, but only the page itself is sorted.
+    pub fn keys_page(&self, after: Option<&str>, limit: usize) -> Vec<String> {
+        let mut keys: Vec<&str> = self
+            .index
+            .keys()
+            .map(String::as_str)
+            .filter(|key| after.is_none_or(|after| *key > after))
+            .collect();
+        if keys.len() > limit {
+            keys.select_nth_unstable(limit);
+            keys.truncate(limit);
+        }
+        keys.sort_unstable();
+        keys.into_iter().map(str::to_owned).collect()
+    }
+
+    /// Returns all live keys, most recently written first.
+    ///
+    /// Keys are ordered by the sequence number of their current value, from
+    /// the index alone. Keys written with the same sequence number, which
+    /// only happens for records written without one (by older versions or
+    /// in the plaintext encoding), are ordered by key, so the order is
+    /// deterministic.
+    pub fn keys_by_recency(&self) -> Vec<String> {
+        let mut keys: Vec<(&str, u64)> = self
+            .index
+            .iter()
+            .map(|(key, cmd_pos)| (key.as_str(), cmd_pos.seq))
+            .collect();
+        keys.sort_unstable_by(|(a_key, a_seq), (b_key, b_seq)| {
+            b_seq.cmp(a_seq).then_with(|| a_key.cmp(b_key))
+        });
+        keys.into_iter().map(|(key, _)| key.to_owned()).collect()
+    }
+
+    /// Returns the number of live keys.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the store contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Releases excess capacity held by the in-memory index, e.g. after
+    /// removing many keys with [`retain`](Self::retain).
+    ///
+    /// This is only a memory-reclaim hint: it doesn't touch the log, and the
+    /// index grows again as needed.
+    pub fn shrink_to_fit(&mut self) {
+        self.index.shrink_to_fit();
+        self.versions.shrink_to_fit();
+    }
+
+    /// Returns an approximate number of bytes of memory used by the index.
+    ///
+    /// This counts the hash table's slots (an entry plus a control byte
+    /// each, for its allocated capacity rather than its length), the bytes
+    /// allocated for each key, and the same for versions kept by
+    /// [`versions_retained`](RuskStoreBuilder::versions_retained). Allocator
+    /// overhead is not included. Nothing is allocated or read from disk.
+    pub fn index_memory_estimate(&self) -> usize {
+        // The table keeps about an eighth of its slots empty.
+        fn table_bytes<K, V, H>(map: &HashMap<K, V, H>) -> usize {
+            map.capacity() * 8 / 7 * (mem::size_of::<(K, V)>() + 1)
+        }
+
+        let index =
+            table_bytes(&self.index) + self.index.keys().map(String::capacity).sum::<usize>();
+        let versions = table_bytes(&self.versions)
+            + self
+                .versions
+                .iter()
+                .map(|(key, versions)| {
+                    key.capacity() + versions.capacity() * mem::size_of::<CommandPos>()
+                })
+                .sum::<usize>();
+        index + versions
+    }
+
+    /// Returns the number of live keys starting with `prefix`, without
+    /// reading any values from disk.
+    ///
+    /// The prefix is matched against keys as stored, i.e. after any
+    /// configured key transform has been applied; an empty prefix counts
+    /// every key, matching [`len`](Self::len).
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.index
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .count()
+    }
+
+    /// Returns the total on-disk size of all live records, in bytes.
+    ///
+    /// This sums record lengths from the index without touching disk, so it
+    /// counts record framing (the length prefix and serialized key) as well
+    /// as the value itself.
+    pub fn total_value_bytes(&self) -> u64 {
+        self.index.values().map(|cmd_pos| cmd_pos.length).sum()
+    }
+
+    /// Returns the directory the store lives in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the compaction generation of the log.
+    ///
+    /// The generation increases every time the log is compacted. Offsets
+    /// reported by the store (e.g. [`Entry::offset`]) are only valid within
+    /// the generation they were obtained in.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// How often a [`StoreHandle`](crate::StoreHandle) checks whether the
+    /// store needs compacting, if at all.
+    pub(crate) fn auto_compact_interval(&self) -> Option<Duration> {
+        self.options.auto_compact_interval
+    }
+
+    /// Returns the format version of the log, as read from its header.
+    ///
+    /// A legacy headerless log reports [`format::LEGACY_VERSION`] (0), and a
+    /// newly created store the version of its configured encoding, e.g.
+    /// [`format::CURRENT_VERSION`] by default. Compaction rewrites the log
+    /// in the configured encoding, so the version can change after it.
+    pub fn format_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns `RuskError::KeyTooLong` if `key` exceeds `max_key_len`.
+    fn check_key_len(&self, key: &str) -> Result<()> {
+        match self.options.max_key_len {
+            Some(max) if key.len() > max => Err(RuskError::KeyTooLong {
+                len: key.len(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks up front that a `Set` can be encoded in the log, so a batch
+    /// fails before any of it is written.
+    fn check_encodable(&self, key: &str, value: &str) -> Result<()> {
+        if self.version == format::PLAINTEXT_VERSION
+            && (self.options.record_created_at
+                || !format::is_plaintext_safe(key)
+                || !format::is_plaintext_safe(value))
+        {
+            return Err(RuskError::PlaintextUnsupported {
+                key: key.to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    fn normalize_key(&self, key: String) -> String {
+        match self.options.key_transform {
+            Some(transform) => transform(&key),
+            None => key,
+        }
+    }
+
+    fn normalize_key_ref<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self.options.key_transform {
+            Some(transform) => Cow::Owned(transform(key)),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    /// Appends a record to the log without flushing the writer.
+    fn append_record(&mut self, record: &Record) -> Result<CommandPos> {
+        let mut data = format::encode_record(record, self.version)?;
+        if self.exceeds_max_log_size(data.len() as u64) {
+            if self.uncompacted > 0 {
+                debug!("log is at its maximum size, compacting");
+                self.auto_compact()?;
+                // Compaction may have changed the log's format version.
+                data = format::encode_record(record, self.version)?;
+            }
+            if self.exceeds_max_log_size(data.len() as u64) {
+                return Err(RuskError::LogFull);
+            }
+        }
+        let offset = self.current_pos;
+        self.append_mirror(&data)?;
+
+        let mut writer = self.writer();
+        // The background flusher may have emptied the buffer since the last
+        // write, in which case nothing is pending any more.
+        let pending = if writer.buffer().is_empty() {
+            0
+        } else {
+            self.pending_writes
+        };
+        let result = write_all_retrying(&mut *writer, &data, self.options.io_retries);
+        drop(writer);
+        self.check_disk_full(result)?;
+        self.pending_writes = pending + 1;
+
+        let entry_len = data.len() as u64;
+        self.current_pos += entry_len;
+        self.metrics.bytes_written_by_user += entry_len;
+        self.metrics.bytes_written_total += entry_len;
+
+        Ok(CommandPos {
+            offset,
+            length: entry_len,
+            seq: format::stored_seq(record.seq, self.version),
+        })
+    }
+
+    /// Appends an encoded record to the mirror, first bringing the mirror up
+    /// to date if it has fallen behind.
+    fn append_mirror(&mut self, data: &[u8]) -> Result<()> {
+        if self
+            .mirror
+            .as_ref()
+            .is_some_and(|mirror| mirror.file.is_none())
+        {
+            self.resync_mirror()?;
+        }
+        let Some(mirror) = &mut self.mirror else {
+            return Ok(());
+        };
+        let Some(file) = &mut mirror.file else {
+            return Ok(());
+        };
+        match file.write_all(data) {
+            Ok(()) => {
+                mirror.len += data.len() as u64;
+                Ok(())
+            }
+            Err(err) => {
+                // Cut off any partial record so the mirror matches the log.
+                if file.set_len(mirror.len).is_err() {
+                    mirror.file = None;
+                }
+                self.mirror_failed(err)
+            }
+        }
+    }
+
+    /// Replaces the mirror with a copy of the log and reopens it for
+    /// appending.
+    fn resync_mirror(&mut self) -> Result<()> {
+        let Some(mirror) = &self.mirror else {
+            return Ok(());
+        };
+        let mirror_path = mirror.path.clone();
+        self.flush_pending()?;
+
+        let log_path = self.log_path();
+        let fsync = self.options.durability == DurabilityMode::Fsync;
+        let copy = || -> io::Result<(File, u64)> {
+            if let Some(dir) = mirror_path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let tmp_path = mirror_path.with_extension(MIRROR_TMP_EXTENSION);
+            let len = fs::copy(&log_path, &tmp_path)?;
+            if fsync {
+                File::open(&tmp_path)?.sync_all()?;
+            }
+            fs::rename(&tmp_path, &mirror_path)?;
+            let file = OpenOptions::new().append(true).open(&mirror_path)?;
+            Ok((file, len))
+        };
+
+        match copy() {
+            Ok((file, len)) => {
+                debug!("rewrote mirror {} ({} bytes)", mirror_path.display(), len);
+                if let Some(mirror) = &mut self.mirror {
+                    mirror.file = Some(file);
+                    mirror.len = len;
+                }
+                Ok(())
+            }
+            Err(err) => self.mirror_failed(err),
+        }
+    }
+
+    /// Applies the configured [`MirrorPolicy`] to a failed mirror write.
+    fn mirror_failed(&mut self, err: io::Error) -> Result<()> {
+        match self.options.mirror_policy {
+            MirrorPolicy::Fatal => Err(RuskError::MirrorWriteFailed(err)),
+            MirrorPolicy::BestEffort => {
+                warn!(
+                    "mirror write failed, mirroring stopped until the store is reopened: {}",
+                    err
+                );
+                self.mirror = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts mirroring the log to `path`, copying the log there unless the
+    /// mirror already matches it.
+    fn attach_mirror(&mut self, path: PathBuf) -> Result<()> {
+        let file = if mirror_matches(&self.log_path(), &path)? {
+            Some(OpenOptions::new().append(true).open(&path)?)
+        } else {
+            None
+        };
+        self.mirror = Some(Mirror {
+            path,
+            file,
+            len: self.current_pos,
+        });
+        if self
+            .mirror
+            .as_ref()
+            .is_some_and(|mirror| mirror.file.is_none())
+        {
+            self.resync_mirror()?;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if appending `len` bytes would grow the log past
+    /// `max_log_size`.
+    fn exceeds_max_log_size(&self, len: u64) -> bool {
+        self.options
+            .max_log_size
+            .is_some_and(|max| self.current_pos + len > max)
+    }
+
+    /// Estimates what [`compact`](Self::compact) would reclaim, without
+    /// rewriting anything.
+    ///
+    /// The estimate is the size of the header plus the lengths of the live
+    /// records (and any retained versions) recorded in the index, so it
+    /// doesn't touch disk. It is exact unless compaction would also change
+    /// the log's encoding, in which case records are re-encoded and the
+    /// compacted log may be somewhat larger or smaller.
+    pub fn compaction_estimate(&self) -> CompactionEstimate {
+        let live: u64 = self
+            .index
+            .values()
+            .chain(self.versions.values().flatten())
+            .map(|cmd_pos| cmd_pos.length)
+            .sum();
+        let estimated_size = format::data_start(self.options.encoding.version()) + live;
+        CompactionEstimate {
+            current_size: self.current_pos,
+            estimated_size,
+            reclaimable: self.current_pos.saturating_sub(estimated_size),
+        }
+    }
+
+    /// Compacts the log by rewriting only the live entries.
+    ///
+    /// This removes all dead space from overwritten or deleted keys. Live
+    /// entries are written sorted by key, so compacting the same data always
+    /// produces the same records in the same order. The logs aren't
+    /// byte-identical, though: the header carries the new generation, and
+    /// each record keeps the sequence number it was originally written with.
+    ///
+    /// If the log has no dead space, is already in the configured encoding
+    /// and no compaction file was left behind, there is nothing to rewrite and
+    /// this returns a report with nothing reclaimed without touching the disk.
+    ///
+    /// Only one compaction may run on a store directory at a time; if another
+    /// handle is already compacting, this returns
+    /// `RuskError::CompactionInProgress` without touching the log.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "rusk.compact",
+            skip_all,
+            fields(bytes_before = self.current_pos, bytes_after = tracing::field::Empty)
+        )
+    )]
+    pub fn compact(&mut self) -> Result<CompactionReport> {
+        if self.uncompacted == 0
+            && self.version == self.options.encoding.version()
+            && !self.sidecar_path(COMPACTION_EXTENSION).exists()
+        {
+            debug!("compaction skipped: no dead space");
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("bytes_after", self.current_pos);
+            return Ok(CompactionReport {
+                bytes_before: self.current_pos,
+                bytes_after: self.current_pos,
+                live_keys: self.index.len(),
+                generation: self.generation,
+                keys_evicted: 0,
+            });
+        }
+
+        let result = self.with_compaction_lock(Self::compact_locked);
+        #[cfg(feature = "tracing")]
+        if let Ok(report) = &result {
+            tracing::Span::current().record("bytes_after", report.bytes_after);
+        }
+
+        result
+    }
+
+    /// Compacts the log and then, if it is still larger than `max_bytes`,
+    /// evicts live keys until it fits, oldest write first.
+    ///
+    /// Evicted keys are removed with tombstones like [`remove`](Self::remove),
+    /// so they stay gone after a crash, and a second compaction then drops
+    /// their records. The report covers both compactions, and
+    /// [`keys_evicted`](CompactionReport::keys_evicted) counts the removed
+    /// keys. If even an empty log exceeds the budget every key is evicted.
+    /// Plain [`compact`](Self::compact) never removes live data.
+    pub fn compact_to_budget(&mut self, max_bytes: u64) -> Result<CompactionReport> {
+        let bytes_before = self.current_pos;
+        let mut report = self.compact()?;
+
+        if self.current_pos > max_bytes {
+            let mut by_age: Vec<_> = self
+                .index
+                .iter()
+                .map(|(key, cmd_pos)| {
+                    let retained = self.versions.get(key).into_iter().flatten();
+                    let length = cmd_pos.length + retained.map(|pos| pos.length).sum::<u64>();
+                    (cmd_pos.seq, key, length)
+                })
+                .collect();
+            by_age.sort_unstable();
+
+            let mut excess = self.current_pos - max_bytes;
+            let mut doomed = Vec::new();
+            for (_, key, length) in by_age {
+                if excess == 0 {
+                    break;
+                }
+                doomed.push(key.clone());
+                excess = excess.saturating_sub(length);
+            }
+
+            debug!("evicting {} keys to fit {} bytes", doomed.len(), max_bytes);
+            let keys_evicted = self.remove_all(doomed)?;
+            self.flush()?;
+            report = self.compact()?;
+            report.keys_evicted = keys_evicted;
+        }
+
+        report.bytes_before = bytes_before;
+        Ok(report)
+    }
+
+    /// Drops every tombstone from the log, returning how many were removed.
+    ///
+    /// Tombstones are only needed until compaction drops the values they
+    /// remove, so this is a compaction that writes only the live values;
+    /// overwritten values are reclaimed along with the tombstones. The
+    /// number of tombstones in the log is tracked in memory, so when there
+    /// are none this returns `0` straight away without touching the disk,
+    /// and calling it often is cheap.
+    ///
+    /// Like `compact`, this returns `RuskError::CompactionInProgress` if
+    /// another handle is already compacting.
+    pub fn purge_tombstones(&mut self) -> Result<usize> {
+        if self.tombstones == 0 {
+            return Ok(0);
+        }
+        let tombstones = self.tombstones;
+        self.with_compaction_lock(Self::compact_locked)?;
+        Ok(tombstones as usize)
+    }
+
+    /// Reclaims space more cheaply than [`compact`](Self::compact) when the
+    /// dead space is mostly at the start of the log.
+    ///
+    /// Records before the first live one can all be dropped: they are
+    /// superseded, or tombstones for keys whose earlier records are also in
+    /// that prefix. Defragmenting copies the rest of the log across verbatim
+    /// instead of rewriting it record by record. If less than half of the
+    /// dead space is in that prefix, or the log isn't in the configured
+    /// encoding, this falls back to a full compaction.
+    ///
+    /// Like `compact`, this returns `RuskError::CompactionInProgress` if
+    /// another handle is already compacting.
+    pub fn defragment(&mut self) -> Result<CompactionReport> {
+        let data_start = format::data_start(self.version);
+        let live_start = self.live_start();
+        let dead_prefix = live_start - data_start;
+
+        if self.version != self.options.encoding.version()
+            || dead_prefix == 0
+            || dead_prefix < self.uncompacted.saturating_sub(dead_prefix)
+        {
+            debug!(
+                "dead prefix is {} of {} uncompacted bytes, compacting instead",
+                dead_prefix, self.uncompacted
+            );
+            return self.compact();
+        }
+
+        self.with_compaction_lock(Self::defragment_locked)
+    }
+
+    /// Returns the offset of the first live record, or the end of the log if
+    /// there are none.
+    fn live_start(&self) -> u64 {
+        self.index
+            .values()
+            .chain(self.versions.values().flatten())
+            .map(|cmd_pos| cmd_pos.offset)
+            .min()
+            .unwrap_or(self.current_pos)
+    }
+
+    /// Runs `compaction` while holding the compaction lock, removing its
+    /// output file if it fails.
+    fn with_compaction_lock(
+        &mut self,
+        compaction: fn(&mut Self) -> Result<CompactionReport>,
+    ) -> Result<CompactionReport> {
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.sidecar_path(COMPACTION_LOCK_EXTENSION))?;
+
+        match lock_file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(RuskError::CompactionInProgress),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        let result = compaction(self);
+        match &result {
+            Ok(report) => {
+                self.metrics.bytes_written_total += report.bytes_after;
+                self.compacted_seq = self.seq;
+                if let Err(err) = self.save_compacted_seq() {
+                    warn!("failed to record compacted sequence number: {}", err);
+                }
+                // The mirror still holds the old log.
+                if let Some(mirror) = &mut self.mirror {
+                    mirror.file = None;
+                }
+                if let Err(err) = self.resync_mirror() {
+                    warn!("failed to rewrite mirror after compaction: {}", err);
+                }
+            }
+            Err(_) => {
+                let _ = fs::remove_file(self.sidecar_path(COMPACTION_EXTENSION));
+            }
+        }
+        result
+    }
+
+    /// Drops the dead prefix of the log. The caller must hold the compaction
+    /// lock.
+    fn defragment_locked(&mut self) -> Result<CompactionReport> {
+        let compaction_path = self.sidecar_path(COMPACTION_EXTENSION);
+        let log_path = self.log_path();
+
+        let data_start = format::data_start(self.version);
+        let live_start = self.live_start();
+        let shift = live_start - data_start;
+        let live_len = self.current_pos - live_start;
+
+        info!(
+            "defragmentation started: dropping {} byte prefix, keeping {} bytes",
+            shift, live_len
+        );
 
         let compact_file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
             .open(&compaction_path)?;
-        let mut compact_writer = BufWriter::new(compact_file);
+        let mut compact_writer =
+            BufWriter::with_capacity(self.options.write_buffer_size, compact_file);
+
+        let header = Header {
+            version: self.version,
+            generation: self.generation + 1,
+        };
+        compact_writer.write_all(&format::encode_header(&header))?;
 
-        let reader_file = File::open(&log_path)?;
-        let mut reader = BufReader::new(reader_file);
+        // Count the tombstones being dropped, so the count stays exact.
+        let mut reader = self.open_reader()?;
+        reader.seek(SeekFrom::Start(data_start))?;
+        let mut dropped_tombstones = 0;
+        let mut pos = data_start;
+        while pos < live_start {
+            let (record, length) = format::read_record(&mut reader, self.version)?;
+            if matches!(record.command, Command::Remove { .. }) {
+                dropped_tombstones += 1;
+            }
+            pos += length;
+        }
 
-        let mut new_index = HashMap::new();
-        let mut new_pos: u64 = 0;
+        let copied = io::copy(&mut (&mut reader).take(live_len), &mut compact_writer)?;
+        if copied != live_len {
+            return Err(RuskError::Corruption { offset: None });
+        }
 
-        for (key, cmd_pos) in &self.index {
+        compact_writer.flush()?;
+        if self.options.durability == DurabilityMode::Fsync {
+            compact_writer.get_ref().sync_all()?;
+        }
+        drop(compact_writer);
+        drop(reader);
+
+        fs::rename(&compaction_path, &log_path)?;
+
+        let writer_file = OpenOptions::new().append(true).open(&log_path)?;
+
+        let report = CompactionReport {
+            bytes_before: self.current_pos,
+            bytes_after: self.current_pos - shift,
+            live_keys: self.index.len(),
+            generation: header.generation,
+            keys_evicted: 0,
+        };
+
+        // Every record after the prefix moves back by the same amount.
+        *self.writer() = BufWriter::with_capacity(self.options.write_buffer_size, writer_file);
+        for cmd_pos in self
+            .index
+            .values_mut()
+            .chain(self.versions.values_mut().flatten())
+        {
+            cmd_pos.offset -= shift;
+        }
+        self.current_pos -= shift;
+        self.uncompacted = self.uncompacted.saturating_sub(shift);
+        self.tombstones -= dropped_tombstones;
+        self.generation = header.generation;
+
+        info!("defragmentation finished: {} bytes reclaimed", shift);
+
+        if let Err(err) = self.save_hint() {
+            warn!("failed to write hint after defragmentation: {}", err);
+        }
+
+        Ok(report)
+    }
+
+    /// Performs compaction. The caller must hold the compaction lock.
+    fn compact_locked(&mut self) -> Result<CompactionReport> {
+        let compaction_path = self.sidecar_path(COMPACTION_EXTENSION);
+        let log_path = self.log_path();
+
+        info!(
+            "compaction started: {} bytes, {} uncompacted",
+            self.current_pos, self.uncompacted
+        );
+
+        let compact_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&compaction_path)?;
+        let mut compact_writer =
+            BufWriter::with_capacity(self.options.write_buffer_size, compact_file);
+
+        let mut reader = self.open_reader()?;
+
+        // Compaction always writes the configured encoding in its current
+        // format, upgrading legacy logs.
+        let header = Header {
+            version: self.options.encoding.version(),
+            generation: self.generation + 1,
+        };
+        let version = header.version;
+        compact_writer.write_all(&format::encode_header(&header))?;
+
+        let mut new_index =
+            HashMap::with_capacity_and_hasher(self.index.len(), self.index.hasher().clone());
+        let mut new_versions = HashMap::with_capacity(self.versions.len());
+        let mut new_pos = format::data_start(version);
+
+        let old_version = self.version;
+        let mut copy_record = |cmd_pos: &CommandPos| -> Result<CommandPos> {
             reader.seek(SeekFrom::Start(cmd_pos.offset))?;
 
-            let mut len_buf = [0u8; 4];
-            reader.read_exact(&mut len_buf)?;
-            let data_len = u32::from_be_bytes(len_buf) as usize;
+            let mut frame = format::read_frame(&mut reader, old_version, cmd_pos.length)?;
+            if old_version != version {
+                let record = format::decode_frame(&frame, old_version, true)?;
+                frame = format::encode_record(&record, version)?;
+            }
+            compact_writer.write_all(&frame)?;
 
-            let mut data_buf = vec![0u8; data_len];
-            reader.read_exact(&mut data_buf)?;
+            let new_cmd_pos = CommandPos {
+                offset: new_pos,
+                length: frame.len() as u64,
+                seq: format::stored_seq(cmd_pos.seq, version),
+            };
+            new_pos += new_cmd_pos.length;
+            Ok(new_cmd_pos)
+        };
 
-            compact_writer.write_all(&len_buf)?;
-            compact_writer.write_all(&data_buf)?;
+        // Write entries in key order so compacting the same data always
+        // produces the same records in the same order, whatever order the
+        // index iterates in. See `compact` for why the bytes still differ.
+        let mut entries: Vec<_> = self.index.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
 
-            let entry_len = 4 + data_len as u64;
-            new_index.insert(
-                key.clone(),
-                CommandPos {
-                    offset: new_pos,
-                    length: entry_len,
-                },
-            );
-            new_pos += entry_len;
+        for (key, cmd_pos) in entries {
+            // Retained versions go first so replay ends on the current value.
+            if let Some(versions) = self.versions.get(key) {
+                let versions = versions
+                    .iter()
+                    .map(&mut copy_record)
+                    .collect::<Result<VecDeque<_>>>()?;
+                new_versions.insert(key.clone(), versions);
+            }
+            new_index.insert(key.clone(), copy_record(cmd_pos)?);
         }
 
         compact_writer.flush()?;
+        // The compacted log must be on disk before it replaces the live one.
+        if self.options.durability == DurabilityMode::Fsync {
+            compact_writer.get_ref().sync_all()?;
+        }
         drop(compact_writer);
         drop(reader);
 
@@ -261,11 +3144,243 @@ impl RuskStore {
 
         let writer_file = OpenOptions::new().append(true).open(&log_path)?;
 
-        self.writer = BufWriter::new(writer_file);
+        info!(
+            "compaction finished: {} bytes reclaimed, {} bytes live",
+            self.current_pos - new_pos,
+            new_pos
+        );
+
+        let report = CompactionReport {
+            bytes_before: self.current_pos,
+            bytes_after: new_pos,
+            live_keys: new_index.len(),
+            generation: header.generation,
+            keys_evicted: 0,
+        };
+
+        *self.writer() = BufWriter::with_capacity(self.options.write_buffer_size, writer_file);
         self.index = new_index;
+        self.versions = new_versions;
         self.current_pos = new_pos;
         self.uncompacted = 0;
+        self.tombstones = 0;
+        self.version = version;
+        self.generation = header.generation;
 
-        Ok(())
+        if let Err(err) = self.save_hint() {
+            warn!("failed to write hint after compaction: {}", err);
+        }
+
+        Ok(report)
+    }
+}
+
+impl<S: BuildHasher + Clone> fmt::Debug for RuskStore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuskStore")
+            .field("path", &self.path)
+            .field("keys", &self.index.len())
+            .field("log_len", &self.current_pos)
+            .field("uncompacted", &self.uncompacted)
+            .field("seq", &self.seq)
+            .field("version", &self.version)
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Opens the store at `path`, runs `f` on it and closes it again, returning
+/// whatever `f` returns.
+///
+/// The store is flushed however `f` exits. If `f` succeeds, the store is
+/// [closed](RuskStore::close) before returning and an error from that is
+/// returned in place of `f`'s value. If `f` fails or panics, the store is
+/// flushed as it is dropped, like any store going out of scope, and `f`'s
+/// error (or panic) is passed through.
+pub fn with_store<T>(
+    path: impl Into<PathBuf>,
+    f: impl FnOnce(&mut RuskStore) -> Result<T>,
+) -> Result<T> {
+    let mut store = RuskStore::open(path)?;
+    let value = f(&mut store)?;
+    store.close()?;
+    Ok(value)
+}
+
+impl<S: BuildHasher + Clone> Drop for RuskStore<S> {
+    fn drop(&mut self) {
+        if !self.closed
+            && let Err(err) = self.shutdown()
+        {
+            warn!("failed to close store on drop: {}", err);
+        }
+    }
+}
+
+/// Returns `true` if the mirror at `mirror_path` has the same length and
+/// header as the log, which is as close as open checks before trusting it.
+fn mirror_matches(log_path: &Path, mirror_path: &Path) -> Result<bool> {
+    let Ok(mirror) = File::open(mirror_path) else {
+        return Ok(false);
+    };
+    let log = File::open(log_path)?;
+    if mirror.metadata()?.len() != log.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut log_header = Vec::new();
+    let mut mirror_header = Vec::new();
+    log.take(format::MAX_HEADER_LEN)
+        .read_to_end(&mut log_header)?;
+    mirror
+        .take(format::MAX_HEADER_LEN)
+        .read_to_end(&mut mirror_header)?;
+    Ok(log_header == mirror_header)
+}
+
+/// Runs `op`, retrying it up to `retries` times, with a doubling delay, while
+/// it fails with `Interrupted` or `WouldBlock`. Any other error, including
+/// running out of disk, is returned at once.
+pub(crate) fn retry_io<T>(retries: usize, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut delay = IO_RETRY_DELAY;
+    for _ in 0..retries {
+        match op() {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                debug!("retrying after transient IO error: {}", err);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Writes all of `data` like `write_all`, retrying transient errors with
+/// [`retry_io`].
+///
+/// Each `write` either consumes some of `data` or fails having consumed
+/// none of it, so a retry picks up exactly where the last successful write
+/// stopped and never writes any part of the record twice. As with
+/// `write_all`, `Interrupted` is retried indefinitely when `retries` is 0.
+pub(crate) fn write_all_retrying(
+    writer: &mut impl Write,
+    mut data: &[u8],
+    retries: usize,
+) -> io::Result<()> {
+    while !data.is_empty() {
+        match retry_io(retries, || writer.write(data)) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(written) => data = &data[written..],
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Background thread that periodically flushes the writer in
+/// [`FlushMode::Manual`] and [`FlushMode::Batched`].
+struct Flusher {
+    shutdown: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Flusher {
+    fn spawn(writer: &Arc<Mutex<BufWriter<File>>>, interval: Duration) -> Self {
+        let writer = Arc::clone(writer);
+        let (shutdown, shutdown_rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = shutdown_rx.recv_timeout(interval) {
+                let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Err(err) = writer.flush() {
+                    warn!("background flush failed: {}", err);
+                }
+            }
+        });
+
+        Flusher { shutdown, handle }
+    }
+
+    fn stop(self) {
+        drop(self.shutdown);
+        let _ = self.handle.join();
+    }
+}
+
+/// Checks that `log_path` is either absent or a regular file, returning
+/// whether it exists.
+///
+/// Directories, broken symlinks and symlink loops are reported as
+/// `RuskError::InvalidLogFile` rather than surfacing a cryptic IO error.
+fn check_log_file(log_path: &Path) -> Result<bool> {
+    match fs::symlink_metadata(log_path) {
+        Ok(_) => match fs::metadata(log_path) {
+            Ok(metadata) if metadata.is_file() => Ok(true),
+            _ => Err(RuskError::InvalidLogFile {
+                path: log_path.to_path_buf(),
+            }),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reads and decodes the record an index entry points at.
+///
+/// Returns `RuskError::Corruption` if the record on disk claims to be longer
+/// than the index recorded.
+pub(crate) fn read_record_at(
+    reader: &mut (impl Read + Seek),
+    cmd_pos: &CommandPos,
+    version: u32,
+    verify: bool,
+) -> Result<Record> {
+    reader.seek(SeekFrom::Start(cmd_pos.offset))?;
+    format::read_frame(reader, version, cmd_pos.length)
+        .and_then(|frame| format::decode_frame(&frame, version, verify))
+        .map_err(|err| match err {
+            RuskError::Corruption { offset: None } => RuskError::Corruption {
+                offset: Some(cmd_pos.offset),
+            },
+            err => err,
+        })
+}
+
+/// Iterator over the commands in a region of the log. See [`RuskStore::tail`].
+struct Tail {
+    reader: BufReader<File>,
+    pos: u64,
+    end: u64,
+    version: u32,
+}
+
+impl Iterator for Tail {
+    type Item = Result<(u64, Command)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.end {
+            return None;
+        }
+
+        let offset = self.pos;
+        match format::read_record(&mut self.reader, self.version) {
+            Ok((record, length)) => {
+                self.pos += length;
+                Some(Ok((offset, record.command)))
+            }
+            Err(err) => {
+                self.pos = self.end;
+                Some(Err(err))
+            }
+        }
     }
 }
+
+#[cfg(test)]
+mod tests;
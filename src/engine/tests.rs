@@ -0,0 +1,597 @@
+use super::*;
+use crate::builder::{Encoding, FlushMode};
+use tempfile::TempDir;
+
+#[test]
+fn create_new_in_fresh_directory() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("store");
+
+    let mut store = RuskStore::create_new(&path).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    assert_eq!(
+        store.get("key".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+}
+
+#[test]
+fn create_new_rejects_existing_log() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    drop(store);
+
+    assert!(matches!(
+        RuskStore::create_new(dir.path()),
+        Err(RuskError::AlreadyExists)
+    ));
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    assert_eq!(
+        store.get("key".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+}
+
+#[test]
+fn open_existing_opens_existing_store() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open_or_create(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    drop(store);
+
+    let mut store = RuskStore::open_existing(dir.path()).unwrap();
+    assert_eq!(
+        store.get("key".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+}
+
+#[test]
+fn open_existing_rejects_missing_store() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("typo");
+
+    assert!(matches!(
+        RuskStore::open_existing(&path),
+        Err(RuskError::NotFound)
+    ));
+    assert!(matches!(
+        RuskStore::open_existing(dir.path()),
+        Err(RuskError::NotFound)
+    ));
+    assert!(!path.exists());
+    assert!(!dir.path().join(LOG_FILE_NAME).exists());
+}
+
+#[test]
+fn compaction_fails_while_another_is_in_progress() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    for i in 0..100 {
+        store.set("key".to_owned(), i.to_string()).unwrap();
+    }
+    store.set("other".to_owned(), "value".to_owned()).unwrap();
+
+    // Stands in for a second handle that is part-way through compacting.
+    let lock = File::create(store.sidecar_path(COMPACTION_LOCK_EXTENSION)).unwrap();
+    lock.lock().unwrap();
+
+    let size = store.current_pos;
+    assert!(matches!(
+        store.compact(),
+        Err(RuskError::CompactionInProgress)
+    ));
+    assert!(!store.sidecar_path(COMPACTION_EXTENSION).exists());
+    assert_eq!(fs::metadata(store.log_path()).unwrap().len(), size);
+    assert_eq!(store.get("key".to_owned()).unwrap().as_deref(), Some("99"));
+
+    drop(lock);
+    let report = store.compact().unwrap();
+    assert!(report.bytes_after < size);
+    assert!(!store.sidecar_path(COMPACTION_EXTENSION).exists());
+    drop(store);
+
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    assert_eq!(store.get("key".to_owned()).unwrap().as_deref(), Some("99"));
+    assert_eq!(
+        store.get("other".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+}
+
+#[test]
+fn verify_on_read_catches_corrupt_value() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::builder(dir.path())
+        .verify_on_read(true)
+        .open()
+        .unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+
+    let log_path = store.log_path();
+    let mut bytes = fs::read(&log_path).unwrap();
+    let at = bytes
+        .windows(5)
+        .position(|window| window == b"value")
+        .unwrap();
+    bytes[at] = b'V';
+    fs::write(&log_path, bytes).unwrap();
+
+    assert!(matches!(
+        store.get("key".to_owned()),
+        Err(RuskError::ChecksumMismatch)
+    ));
+}
+
+#[test]
+fn unsupported_version_is_rejected() {
+    let dir = TempDir::new().unwrap();
+    let log_path = dir.path().join(LOG_FILE_NAME);
+    let header = format::encode_header(&Header {
+        version: 99,
+        generation: 0,
+    });
+    fs::write(&log_path, header).unwrap();
+
+    assert!(matches!(
+        RuskStore::open(dir.path()),
+        Err(RuskError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        RuskStore::probe(dir.path()),
+        Err(RuskError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(
+        RuskStore::from_file(File::open(&log_path).unwrap()),
+        Err(RuskError::UnsupportedVersion(99))
+    ));
+    let dest = dir.path().join("repaired");
+    assert!(matches!(
+        RuskStore::repair(dir.path(), &dest, RepairPolicy::default()),
+        Err(RuskError::UnsupportedVersion(99))
+    ));
+}
+
+#[test]
+fn long_overwrite_run_counts_all_but_last_as_dead() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    for i in 0..10_000 {
+        store.set("counter".to_owned(), i.to_string()).unwrap();
+    }
+
+    let data_start = format::data_start(store.version);
+    let live = store.index["counter"].length;
+    assert_eq!(store.uncompacted, store.current_pos - data_start - live);
+    drop(store);
+
+    // Replay must arrive at the same accounting.
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    assert_eq!(store.uncompacted, store.current_pos - data_start - live);
+
+    let report = store.compact().unwrap();
+    assert_eq!(report.bytes_after, data_start + live);
+    assert_eq!(
+        fs::metadata(store.log_path()).unwrap().len(),
+        data_start + live
+    );
+    assert_eq!(store.uncompacted, 0);
+    assert_eq!(
+        store.get("counter".to_owned()).unwrap().as_deref(),
+        Some("9999")
+    );
+}
+
+#[test]
+fn oversized_length_prefix_is_rejected_without_allocating() {
+    let mut bytes = Cursor::new(u32::MAX.to_be_bytes().repeat(2));
+    assert!(matches!(
+        format::read_frame(&mut bytes, format::CURRENT_VERSION, 8),
+        Err(RuskError::Corruption { .. })
+    ));
+}
+
+#[test]
+fn oversized_length_prefix_at_end_of_log_is_cut_off_on_replay() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    let log_path = store.log_path();
+    let size = store.current_pos;
+    drop(store);
+
+    let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+    log.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    log.write_all(&[0; 8]).unwrap();
+    drop(log);
+
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    assert_eq!(
+        store.get("key".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+    assert_eq!(fs::metadata(&log_path).unwrap().len(), size);
+}
+
+#[test]
+fn oversized_length_prefix_fails_get_with_corruption() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    let offset = store.index["key"].offset;
+
+    let mut log = OpenOptions::new()
+        .write(true)
+        .open(store.log_path())
+        .unwrap();
+    log.seek(SeekFrom::Start(offset)).unwrap();
+    log.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    drop(log);
+
+    assert!(matches!(
+        store.get("key".to_owned()),
+        Err(RuskError::Corruption { .. })
+    ));
+}
+
+#[test]
+fn cache_stats_count_hits_misses_and_evictions() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("a".to_owned(), "1".to_owned()).unwrap();
+    store.get("a".to_owned()).unwrap();
+    assert_eq!(store.cache_stats(), CacheStats::default());
+    assert_eq!(store.cache_stats().capacity, None);
+    drop(store);
+
+    let mut store = RuskStore::builder(dir.path())
+        .value_cache(1)
+        .open()
+        .unwrap();
+    store.set("b".to_owned(), "2".to_owned()).unwrap();
+    store.get("a".to_owned()).unwrap();
+    store.get("a".to_owned()).unwrap();
+    store.get("b".to_owned()).unwrap();
+    assert_eq!(
+        store.cache_stats(),
+        CacheStats {
+            hits: 1,
+            misses: 2,
+            evictions: 1,
+            size: 1,
+            capacity: Some(1),
+        }
+    );
+
+    store.reset_cache_stats();
+    assert_eq!(
+        store.cache_stats(),
+        CacheStats {
+            size: 1,
+            capacity: Some(1),
+            ..CacheStats::default()
+        }
+    );
+}
+
+#[test]
+fn defragment_drops_dead_prefix_and_copies_live_tail_verbatim() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    for i in 0..100 {
+        store.set("scratch".to_owned(), i.to_string()).unwrap();
+    }
+    store.remove("scratch".to_owned()).unwrap();
+    // Written out of key order, so a full compaction would reorder them.
+    for i in (0..10).rev() {
+        store.set(format!("key{}", i), i.to_string()).unwrap();
+    }
+
+    let log_path = store.log_path();
+    let before = fs::read(&log_path).unwrap();
+    let live_start = store.live_start() as usize;
+    let tail = before[live_start..].to_vec();
+
+    let report = store.defragment().unwrap();
+    assert_eq!(report.bytes_before, before.len() as u64);
+    assert_eq!(
+        report.bytes_after,
+        format::data_start(store.version) + tail.len() as u64
+    );
+    assert_eq!(store.uncompacted, 0);
+    assert_eq!(store.tombstones, 0);
+
+    let after = fs::read(&log_path).unwrap();
+    assert_eq!(after.len() as u64, report.bytes_after);
+    assert!(after.ends_with(&tail));
+    drop(store);
+
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    assert_eq!(store.len(), 10);
+    assert_eq!(store.get("scratch".to_owned()).unwrap(), None);
+    for i in 0..10 {
+        assert_eq!(store.get(format!("key{}", i)).unwrap(), Some(i.to_string()));
+    }
+}
+
+#[test]
+fn set_batch_with_duplicate_key_keeps_last_and_counts_first_as_dead() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store
+        .set_batch([
+            ("k".to_owned(), "1".to_owned()),
+            ("k".to_owned(), "2".to_owned()),
+        ])
+        .unwrap();
+
+    assert_eq!(store.get("k".to_owned()).unwrap().as_deref(), Some("2"));
+    assert_eq!(store.len(), 1);
+
+    let records: Vec<Command> = store
+        .tail(format::data_start(store.version))
+        .unwrap()
+        .map(|record| record.unwrap().1)
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert!(matches!(&records[0], Command::Set { key, value, .. } if key == "k" && value == "1"));
+    assert!(matches!(&records[1], Command::Set { key, value, .. } if key == "k" && value == "2"));
+
+    // The first record is all that precedes the live one.
+    let data_start = format::data_start(store.version);
+    let live = store.index["k"];
+    assert_eq!(store.uncompacted, live.offset - data_start);
+
+    let report = store.compact().unwrap();
+    assert_eq!(report.bytes_after, data_start + live.length);
+}
+
+#[test]
+fn plaintext_index_matches_log() {
+    let dir = TempDir::new().unwrap();
+    let plaintext = || {
+        RuskStore::builder(dir.path())
+            .encoding(Encoding::Plaintext)
+            .index_cache(true)
+    };
+
+    let mut store = plaintext().open().unwrap();
+    store.set("a".to_owned(), "1".to_owned()).unwrap();
+    store.verify_index().unwrap();
+    store.set("a".to_owned(), "2".to_owned()).unwrap();
+    store.set("b".to_owned(), "3".to_owned()).unwrap();
+    store.compact().unwrap();
+    store.verify_index().unwrap();
+    drop(store);
+
+    // Reopened from the hint written on drop.
+    let mut store = plaintext().open().unwrap();
+    store.verify_index().unwrap();
+    assert_eq!(store.get("a".to_owned()).unwrap().as_deref(), Some("2"));
+}
+
+#[test]
+fn compacting_into_plaintext_keeps_index_consistent() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("a".to_owned(), "1".to_owned()).unwrap();
+    drop(store);
+
+    let mut store = RuskStore::builder(dir.path())
+        .encoding(Encoding::Plaintext)
+        .open()
+        .unwrap();
+    store.compact().unwrap();
+    assert_eq!(store.version, format::PLAINTEXT_VERSION);
+    store.verify_index().unwrap();
+}
+
+#[test]
+fn plaintext_read_at_rejects_offset_inside_a_line() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::builder(dir.path())
+        .encoding(Encoding::Plaintext)
+        .open()
+        .unwrap();
+    store.set("first".to_owned(), "1".to_owned()).unwrap();
+    let offset = store.set_at("key".to_owned(), "value".to_owned()).unwrap();
+
+    assert!(matches!(
+        store.read_at(offset).unwrap(),
+        Command::Set { key, value, .. } if key == "key" && value == "value"
+    ));
+    assert!(matches!(
+        store.read_at(offset + 1),
+        Err(RuskError::Corruption { .. })
+    ));
+    assert!(matches!(
+        store.tail(offset + 1).map(|_| ()),
+        Err(RuskError::Corruption { .. })
+    ));
+    assert_eq!(store.tail(offset).unwrap().count(), 1);
+}
+
+#[test]
+fn read_at_rejects_offset_past_end_without_overflowing() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+
+    for offset in [store.current_pos, u64::MAX - 1, u64::MAX] {
+        assert!(matches!(
+            store.read_at(offset),
+            Err(RuskError::Corruption { .. })
+        ));
+    }
+}
+
+#[test]
+fn read_at_rejects_misaligned_offset() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("first".to_owned(), "value".to_owned()).unwrap();
+    let offset = store.set_at("key".to_owned(), "value".to_owned()).unwrap();
+    store.set("last".to_owned(), "value".to_owned()).unwrap();
+
+    assert!(matches!(
+        store.read_at(offset).unwrap(),
+        Command::Set { key, .. } if key == "key"
+    ));
+    for misaligned in [offset + 1, offset + 4, offset - 1] {
+        assert!(matches!(
+            store.read_at(misaligned),
+            Err(RuskError::Corruption { .. })
+        ));
+    }
+}
+
+#[test]
+fn overwrites_and_compaction_amplify_writes() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    for i in 0..1000 {
+        store.set(format!("key{}", i % 10), i.to_string()).unwrap();
+    }
+    let metrics = store.metrics();
+    assert_eq!(metrics.bytes_written_by_user, metrics.bytes_written_total);
+    assert_eq!(metrics.write_amplification(), Some(1.0));
+
+    let report = store.compact().unwrap();
+    // Nothing left to reclaim, so this writes nothing.
+    store.compact().unwrap();
+    store.set("key0".to_owned(), "again".to_owned()).unwrap();
+    let report_again = store.compact().unwrap();
+
+    let after = store.metrics();
+    assert_eq!(
+        after.bytes_written_total - after.bytes_written_by_user,
+        report.bytes_after + report_again.bytes_after
+    );
+    assert!(after.write_amplification().unwrap() > 1.0);
+}
+
+#[test]
+fn get_past_truncated_log_reports_corruption_at_offset() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("kept".to_owned(), "1".to_owned()).unwrap();
+    store.set("lost".to_owned(), "2".to_owned()).unwrap();
+    let lost = store.index["lost"];
+
+    // Truncated behind the store's back, part-way into the last record.
+    OpenOptions::new()
+        .write(true)
+        .open(store.log_path())
+        .unwrap()
+        .set_len(lost.offset + 1)
+        .unwrap();
+
+    assert_eq!(store.get("kept".to_owned()).unwrap().as_deref(), Some("1"));
+    match store.get("lost".to_owned()) {
+        Err(RuskError::Corruption { offset }) => assert_eq!(offset, Some(lost.offset)),
+        other => panic!("expected Corruption, got {:?}", other),
+    }
+}
+
+#[test]
+fn get_with_stale_index_entry_reports_corruption_at_offset() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("key".to_owned(), "value".to_owned()).unwrap();
+    let stale = CommandPos {
+        offset: store.current_pos + 100,
+        ..store.index["key"]
+    };
+    store.index.insert("stale".to_owned(), stale);
+
+    assert_eq!(
+        store.get("key".to_owned()).unwrap().as_deref(),
+        Some("value")
+    );
+    match store.get("stale".to_owned()) {
+        Err(RuskError::Corruption { offset }) => assert_eq!(offset, Some(stale.offset)),
+        other => panic!("expected Corruption, got {:?}", other),
+    }
+}
+
+#[test]
+fn corrupt_hint_falls_back_to_replay() {
+    let dir = TempDir::new().unwrap();
+    let cached = || RuskStore::builder(dir.path()).index_cache(true);
+
+    let mut store = cached().open().unwrap();
+    for i in 0..10 {
+        store.set(format!("key{}", i), i.to_string()).unwrap();
+    }
+    store.remove("key3".to_owned()).unwrap();
+    let hint_path = store.sidecar_path(HINT_EXTENSION);
+    drop(store);
+
+    // Point an entry somewhere else while keeping the hint well-formed, so
+    // only the checksum can tell.
+    let mut bytes = fs::read(&hint_path).unwrap();
+    let at = bytes
+        .windows(9)
+        .position(|window| window == b"\"offset\":")
+        .unwrap()
+        + 9;
+    bytes[at] = if bytes[at] == b'1' { b'2' } else { b'1' };
+    fs::write(&hint_path, bytes).unwrap();
+    assert!(Hint::load(&hint_path).is_none());
+
+    let mut store = cached().open().unwrap();
+    store.verify_index().unwrap();
+    assert_eq!(store.len(), 9);
+    assert_eq!(store.get("key3".to_owned()).unwrap(), None);
+    for i in (0..10).filter(|&i| i != 3) {
+        assert_eq!(store.get(format!("key{}", i)).unwrap(), Some(i.to_string()));
+    }
+}
+
+#[test]
+fn from_file_reads_log_from_memory() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::open(dir.path()).unwrap();
+    store.set("a".to_owned(), "1".to_owned()).unwrap();
+    store.set("b".to_owned(), "2".to_owned()).unwrap();
+    store.remove("a".to_owned()).unwrap();
+    let log = fs::read(store.log_path()).unwrap();
+    drop(store);
+
+    let reader = RuskStore::from_file(Cursor::new(log)).unwrap();
+    assert_eq!(reader.len(), 1);
+    assert_eq!(reader.get("a").unwrap(), None);
+    assert_eq!(reader.get("b").unwrap().as_deref(), Some("2"));
+
+    let clone = reader.clone();
+    thread::spawn(move || assert_eq!(clone.get("b").unwrap().as_deref(), Some("2")))
+        .join()
+        .unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn checkpoint_rolls_back_when_disk_is_full() {
+    let dir = TempDir::new().unwrap();
+    let mut store = RuskStore::builder(dir.path())
+        .flush_mode(FlushMode::Manual)
+        .open()
+        .unwrap();
+    store.set("kept".to_owned(), "1".to_owned()).unwrap();
+    store.flush().unwrap();
+
+    // Every write to /dev/full fails with ENOSPC.
+    let full = OpenOptions::new().write(true).open("/dev/full").unwrap();
+    *store.writer() = BufWriter::new(full);
+    store.set("lost".to_owned(), "2".to_owned()).unwrap();
+
+    assert!(matches!(store.checkpoint(), Err(RuskError::DiskFull)));
+    assert!(!store.sidecar_path(HINT_EXTENSION).exists());
+    assert_eq!(store.get("lost".to_owned()).unwrap(), None);
+    assert_eq!(store.get("kept".to_owned()).unwrap().as_deref(), Some("1"));
+
+    store.set("after".to_owned(), "3".to_owned()).unwrap();
+    store.checkpoint().unwrap();
+    assert_eq!(store.get("after".to_owned()).unwrap().as_deref(), Some("3"));
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::path::PathBuf;
 
 pub type Result<T> = std::result::Result<T, RuskError>;
 
@@ -9,9 +10,53 @@ pub enum RuskError {
     /// Serialization/deserialization error
     Serde(serde_json::Error),
     /// Key not found in the store
-    KeyNotFound,
+    KeyNotFound(String),
     /// Unexpected command type during read
     UnexpectedCommand,
+    /// A store already exists at the given path
+    AlreadyExists,
+    /// No store exists at the given path
+    NotFound,
+    /// The log path exists but is not a regular file
+    InvalidLogFile { path: PathBuf },
+    /// Another compaction is already running on this store
+    CompactionInProgress,
+    /// A record's checksum doesn't match its contents
+    ChecksumMismatch,
+    /// A record's declared length runs past the end of the log, or its
+    /// contents are malformed. `offset` is where the record starts in the
+    /// log, if the error came from reading it there rather than from
+    /// decoding bytes passed to [`format`](crate::format)
+    Corruption { offset: Option<u64> },
+    /// A write would grow the log past its configured maximum size
+    LogFull,
+    /// An index entry doesn't point at a valid `Set` record for its key
+    IndexMismatch { key: String, offset: u64 },
+    /// A key is longer than the store's configured maximum, in bytes
+    KeyTooLong { len: usize, max: usize },
+    /// The disk is full. Writes that append to the log, `flush` and
+    /// `checkpoint` roll back any partially written record first; other
+    /// writes, e.g. of a compacted log or hint file, leave the log as it was
+    DiskFull,
+    /// Writing to the mirror log failed
+    MirrorWriteFailed(io::Error),
+    /// A record can't be stored in the plaintext encoding: its key or value
+    /// contains a tab or newline, or it has metadata or a creation time
+    PlaintextUnsupported { key: String },
+    /// Changes after `seq` can't be listed because compaction may have
+    /// dropped records up to `compacted`
+    SeqCompacted { seq: u64, compacted: u64 },
+    /// A key incremented as a counter holds a value that isn't an integer
+    NotACounter { key: String },
+    /// Incrementing a counter would overflow an `i64`
+    CounterOverflow { key: String },
+    /// A line of an NDJSON import isn't a valid entry
+    InvalidImportLine { line: usize },
+    /// The log's header names a format version this build can't read
+    UnsupportedVersion(u32),
+    /// An `auto_compact_interval` was set on a store opened without a
+    /// `StoreHandle` to run it
+    AutoCompactRequiresHandle,
 }
 
 impl std::fmt::Display for RuskError {
@@ -19,8 +64,51 @@ impl std::fmt::Display for RuskError {
         match self {
             RuskError::Io(err) => write!(f, "IO error: {}", err),
             RuskError::Serde(err) => write!(f, "Serialization error: {}", err),
-            RuskError::KeyNotFound => write!(f, "Key not found"),
+            RuskError::KeyNotFound(key) => write!(f, "Key not found: {}", key),
             RuskError::UnexpectedCommand => write!(f, "Unexpected command"),
+            RuskError::AlreadyExists => write!(f, "Store already exists"),
+            RuskError::NotFound => write!(f, "Store not found"),
+            RuskError::InvalidLogFile { path } => {
+                write!(f, "Log file is not a regular file: {}", path.display())
+            }
+            RuskError::CompactionInProgress => write!(f, "Compaction already in progress"),
+            RuskError::ChecksumMismatch => write!(f, "Checksum mismatch"),
+            RuskError::Corruption { offset: None } => write!(f, "Corrupt record"),
+            RuskError::Corruption {
+                offset: Some(offset),
+            } => write!(f, "Corrupt record at offset {}", offset),
+            RuskError::LogFull => write!(f, "Log is full"),
+            RuskError::IndexMismatch { key, offset } => {
+                write!(
+                    f,
+                    "Index entry for {} doesn't match the log at offset {}",
+                    key, offset
+                )
+            }
+            RuskError::KeyTooLong { len, max } => {
+                write!(f, "Key too long: {} bytes (maximum {})", len, max)
+            }
+            RuskError::DiskFull => write!(f, "Disk is full"),
+            RuskError::MirrorWriteFailed(err) => write!(f, "Mirror write failed: {}", err),
+            RuskError::PlaintextUnsupported { key } => {
+                write!(f, "Can't store {} in the plaintext encoding", key)
+            }
+            RuskError::SeqCompacted { seq, compacted } => {
+                write!(
+                    f,
+                    "Changes since seq {} are unavailable: the log was compacted through seq {}",
+                    seq, compacted
+                )
+            }
+            RuskError::NotACounter { key } => write!(f, "Value of {} is not an integer", key),
+            RuskError::CounterOverflow { key } => write!(f, "Counter {} would overflow", key),
+            RuskError::InvalidImportLine { line } => write!(f, "Invalid entry on line {}", line),
+            RuskError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported log format version {}", version)
+            }
+            RuskError::AutoCompactRequiresHandle => {
+                write!(f, "Auto-compaction requires opening the store as a handle")
+            }
         }
     }
 }
@@ -29,7 +117,10 @@ impl std::error::Error for RuskError {}
 
 impl From<io::Error> for RuskError {
     fn from(err: io::Error) -> Self {
-        RuskError::Io(err)
+        match err.kind() {
+            io::ErrorKind::StorageFull => RuskError::DiskFull,
+            _ => RuskError::Io(err),
+        }
     }
 }
 
@@ -38,3 +129,28 @@ impl From<serde_json::Error> for RuskError {
         RuskError::Serde(err)
     }
 }
+
+/// Converts to an `io::Error` so `?` works in functions returning
+/// `io::Result`. `Io` errors are passed through unchanged; other errors are
+/// wrapped with the closest matching `ErrorKind`.
+impl From<RuskError> for io::Error {
+    fn from(err: RuskError) -> Self {
+        let kind = match err {
+            RuskError::Io(err) => return err,
+            RuskError::KeyNotFound(_) | RuskError::NotFound => io::ErrorKind::NotFound,
+            RuskError::AlreadyExists => io::ErrorKind::AlreadyExists,
+            RuskError::KeyTooLong { .. }
+            | RuskError::PlaintextUnsupported { .. }
+            | RuskError::AutoCompactRequiresHandle => io::ErrorKind::InvalidInput,
+            RuskError::NotACounter { .. }
+            | RuskError::InvalidImportLine { .. }
+            | RuskError::UnsupportedVersion(_) => io::ErrorKind::InvalidData,
+            RuskError::DiskFull => io::ErrorKind::StorageFull,
+            RuskError::Serde(_) | RuskError::ChecksumMismatch | RuskError::Corruption { .. } => {
+                io::ErrorKind::InvalidData
+            }
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,643 @@
+//! The on-disk record format.
+//!
+//! A log starts with a header identifying its format version and compaction
+//! generation:
+//! ```text
+//! [4 bytes: magic "RUSK"] [4 bytes: version (u32 big-endian)] [8 bytes: generation (u64 big-endian)]
+//! ```
+//! followed by a sequence of records, each framed as:
+//! ```text
+//! [4 bytes: length (u32 big-endian)] [4 bytes: CRC32 of data] [N bytes: JSON-serialized command]
+//! ```
+//!
+//! In versions `1` and `2` the data is a JSON-serialized command. Version `3`
+//! uses a compact binary encoding instead, which avoids JSON's quoting and
+//! escaping for logs dominated by small keys and values:
+//! ```text
+//! Set:    [1 byte: 0] [8 bytes: seq] [4 bytes: key length] [key] [4 bytes: value length] [value]
+//! Remove: [1 byte: 1] [8 bytes: seq] [4 bytes: key length] [key]
+//! Set with metadata: as Set with tag 2, followed by [4 bytes: meta length] [meta]
+//! ```
+//! A `Set` that records its creation time uses tag 3 (or 4 with metadata)
+//! and ends with `[8 bytes: created_at]`. All integers are big-endian.
+//!
+//! Version `4` is a plaintext encoding meant to be read with ordinary text
+//! tools. Records are unframed lines:
+//! ```text
+//! Set:    key\tvalue\n
+//! Remove: -key\n
+//! ```
+//! It has no checksums and doesn't store sequence numbers, metadata or
+//! creation times, and keys and values can't contain tabs or newlines.
+//!
+//! Version `1` logs have the same framing but an 8-byte header without the
+//! generation, which reads as `0`. Logs written before the header was
+//! introduced have no header and no checksum; they are reported as version
+//! `0` and framed as:
+//! ```text
+//! [4 bytes: length (u32 big-endian)] [N bytes: JSON-serialized command]
+//! ```
+//!
+//! These helpers are the same ones the engine uses to read and write its log,
+//! so external tools built on them always agree with the store.
+
+use std::borrow::Cow;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, RuskError};
+
+/// Magic bytes at the start of every versioned log.
+pub const MAGIC: [u8; 4] = *b"RUSK";
+
+/// Size of the largest log header across all versions.
+pub const MAX_HEADER_LEN: u64 = 16;
+
+/// Version of headerless logs, whose records carry no checksum.
+pub const LEGACY_VERSION: u32 = 0;
+
+/// Latest version with JSON-encoded records, written by default.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Version with binary-encoded records.
+pub const BINARY_VERSION: u32 = 3;
+
+/// Version with plaintext records, one line each.
+pub const PLAINTEXT_VERSION: u32 = 4;
+
+const SET_TAG: u8 = 0;
+const REMOVE_TAG: u8 = 1;
+const SET_WITH_META_TAG: u8 = 2;
+const SET_CREATED_TAG: u8 = 3;
+const SET_WITH_META_CREATED_TAG: u8 = 4;
+
+/// Size of the length prefix that precedes every record.
+pub const LENGTH_PREFIX_LEN: u64 = 4;
+
+/// Size of the checksum that follows the length prefix in versioned logs.
+pub const CHECKSUM_LEN: u64 = 4;
+
+/// A command recorded in the log.
+///
+/// This is the stable public form of the records in the log, independent of
+/// how they are serialized on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Command {
+    Set {
+        key: String,
+        value: String,
+        /// Optional metadata stored alongside the value, e.g. a content type.
+        /// Records written before metadata was introduced report `None`.
+        meta: Option<String>,
+        /// When the record was written, in milliseconds since the Unix
+        /// epoch, if the store was recording creation times. `None` means
+        /// unknown, including for records written before this was introduced.
+        created_at: Option<u64>,
+    },
+    Remove {
+        key: String,
+    },
+}
+
+impl Command {
+    /// Returns the key this command applies to.
+    pub fn key(&self) -> &str {
+        match self {
+            Command::Set { key, .. } | Command::Remove { key } => key,
+        }
+    }
+}
+
+/// A single record in the log: a command and the sequence number it was
+/// written with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Sequence number of the write. Records written before sequence numbers
+    /// were introduced report `0`.
+    pub seq: u64,
+    pub command: Command,
+}
+
+/// The serialized form of a record.
+#[derive(Serialize, Deserialize)]
+enum LogCommand<'a> {
+    Set {
+        key: Cow<'a, str>,
+        value: Cow<'a, str>,
+        #[serde(default)]
+        seq: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        meta: Option<Cow<'a, str>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        created_at: Option<u64>,
+    },
+    Remove {
+        key: Cow<'a, str>,
+        #[serde(default)]
+        seq: u64,
+    },
+}
+
+impl<'a> From<&'a Record> for LogCommand<'a> {
+    fn from(record: &'a Record) -> Self {
+        match &record.command {
+            Command::Set {
+                key,
+                value,
+                meta,
+                created_at,
+            } => LogCommand::Set {
+                key: Cow::Borrowed(key),
+                value: Cow::Borrowed(value),
+                seq: record.seq,
+                meta: meta.as_deref().map(Cow::Borrowed),
+                created_at: *created_at,
+            },
+            Command::Remove { key } => LogCommand::Remove {
+                key: Cow::Borrowed(key),
+                seq: record.seq,
+            },
+        }
+    }
+}
+
+impl From<LogCommand<'_>> for Record {
+    fn from(cmd: LogCommand<'_>) -> Self {
+        match cmd {
+            LogCommand::Set {
+                key,
+                value,
+                seq,
+                meta,
+                created_at,
+            } => Record {
+                seq,
+                command: Command::Set {
+                    key: key.into_owned(),
+                    value: value.into_owned(),
+                    meta: meta.map(Cow::into_owned),
+                    created_at,
+                },
+            },
+            LogCommand::Remove { key, seq } => Record {
+                seq,
+                command: Command::Remove {
+                    key: key.into_owned(),
+                },
+            },
+        }
+    }
+}
+
+/// The header at the start of a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    /// Number of times the log has been compacted. Incremented every time
+    /// compaction rewrites the log, invalidating offsets into the old one.
+    pub generation: u64,
+}
+
+/// Returns the size of the header of a log of the given version.
+pub fn header_len(version: u32) -> u64 {
+    match version {
+        LEGACY_VERSION => 0,
+        1 => 8,
+        _ => MAX_HEADER_LEN,
+    }
+}
+
+/// Encodes a log header.
+pub fn encode_header(header: &Header) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(header_len(header.version) as usize);
+    if header.version == LEGACY_VERSION {
+        return buf;
+    }
+
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&header.version.to_be_bytes());
+    if header.version >= 2 {
+        buf.extend_from_slice(&header.generation.to_be_bytes());
+    }
+    buf
+}
+
+/// Returns `RuskError::UnsupportedVersion` if `version` is newer than any
+/// format this build can read.
+pub fn check_version(version: u32) -> Result<()> {
+    if version > PLAINTEXT_VERSION {
+        return Err(RuskError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Returns the sequence number a record written with `seq` reads back with
+/// in a log of `version`. Plaintext records don't store one, so they always
+/// read back as `0`.
+pub(crate) fn stored_seq(seq: u64, version: u32) -> u64 {
+    match version {
+        PLAINTEXT_VERSION => 0,
+        _ => seq,
+    }
+}
+
+/// Parses the header from the first bytes of a log.
+///
+/// Returns `None` if `bytes` doesn't start with a complete header, which
+/// means the log is either a legacy headerless log or has a torn header (see
+/// [`is_torn_header`]).
+pub fn decode_header(bytes: &[u8]) -> Option<Header> {
+    if bytes.len() < 8 || bytes[..4] != MAGIC {
+        return None;
+    }
+
+    let version = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+    if (bytes.len() as u64) < header_len(version) {
+        return None;
+    }
+
+    let generation = match version {
+        1 => 0,
+        _ => u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+    };
+
+    Some(Header {
+        version,
+        generation,
+    })
+}
+
+/// Returns `true` if `bytes` (the entire contents of a log) is a header that
+/// was only partially written, e.g. due to a crash while creating the log.
+pub fn is_torn_header(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return false;
+    }
+    if bytes.len() < MAGIC.len() {
+        return MAGIC.starts_with(bytes);
+    }
+    if bytes[..4] != MAGIC {
+        return false;
+    }
+    if bytes.len() < 8 {
+        return true;
+    }
+
+    let version = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+    (bytes.len() as u64) < header_len(version)
+}
+
+/// Returns the offset of the first record in a log of the given version.
+pub fn data_start(version: u32) -> u64 {
+    header_len(version)
+}
+
+/// Returns the number of framing bytes that precede each record's data.
+pub fn frame_overhead(version: u32) -> u64 {
+    match version {
+        LEGACY_VERSION => LENGTH_PREFIX_LEN,
+        PLAINTEXT_VERSION => 0,
+        _ => LENGTH_PREFIX_LEN + CHECKSUM_LEN,
+    }
+}
+
+/// Encodes a record into its framed on-disk form for a log of the given
+/// version.
+///
+/// In the plaintext encoding, returns `RuskError::PlaintextUnsupported` if
+/// the record can't be represented.
+pub fn encode_record(record: &Record, version: u32) -> Result<Vec<u8>> {
+    if version == PLAINTEXT_VERSION {
+        return encode_plaintext(record);
+    }
+
+    let data = if version == BINARY_VERSION {
+        encode_binary(record)
+    } else {
+        serde_json::to_vec(&LogCommand::from(record))?
+    };
+    let data_len = data.len() as u32;
+
+    let mut buf = Vec::with_capacity(frame_overhead(version) as usize + data.len());
+    buf.extend_from_slice(&data_len.to_be_bytes());
+    if version != LEGACY_VERSION {
+        buf.extend_from_slice(&crc32fast::hash(&data).to_be_bytes());
+    }
+    buf.extend_from_slice(&data);
+
+    Ok(buf)
+}
+
+/// Decodes the framed record at the start of `bytes`, returning it along with
+/// the number of bytes it occupied.
+///
+/// Returns `RuskError::ChecksumMismatch` if the record's checksum doesn't
+/// match its data, or `RuskError::Corruption` if its length prefix claims
+/// more bytes than `bytes` holds.
+pub fn decode_record(mut bytes: &[u8], version: u32) -> Result<(Record, usize)> {
+    let limit = bytes.len() as u64;
+    let frame = read_frame(&mut bytes, version, limit)?;
+    let record = decode_frame(&frame, version, true)?;
+    Ok((record, frame.len()))
+}
+
+/// Reads the next framed record from `reader`, returning it along with the
+/// number of bytes it occupied.
+///
+/// Returns `RuskError::ChecksumMismatch` if the record's checksum doesn't
+/// match its data, or `RuskError::Corruption` if `reader` ends before the
+/// record does.
+pub fn read_record(reader: &mut impl Read, version: u32) -> Result<(Record, u64)> {
+    let frame = read_frame(reader, version, u64::MAX)?;
+    let record = decode_frame(&frame, version, true)?;
+    Ok((record, frame.len() as u64))
+}
+
+/// Reads the next record from `reader` as raw framed bytes, without decoding.
+///
+/// The length prefix is untrusted. A frame that would be longer than `limit`
+/// (the number of bytes the caller knows to be available) is rejected before
+/// anything is allocated, and the frame is read incrementally rather than
+/// allocated up front, so a prefix claiming more bytes than remain returns
+/// `RuskError::Corruption` instead of attempting a huge allocation.
+pub(crate) fn read_frame(reader: &mut impl Read, version: u32, limit: u64) -> Result<Vec<u8>> {
+    if version == PLAINTEXT_VERSION {
+        return read_line(reader, limit);
+    }
+
+    let overhead = frame_overhead(version);
+
+    let mut len_buf = [0u8; LENGTH_PREFIX_LEN as usize];
+    reader.read_exact(&mut len_buf)?;
+    let data_len = u32::from_be_bytes(len_buf) as u64;
+    if overhead + data_len > limit {
+        return Err(RuskError::Corruption { offset: None });
+    }
+
+    let body_len = overhead - LENGTH_PREFIX_LEN + data_len;
+    let mut frame = Vec::with_capacity(LENGTH_PREFIX_LEN as usize);
+    frame.extend_from_slice(&len_buf);
+    reader.take(body_len).read_to_end(&mut frame)?;
+    if (frame.len() as u64) < overhead + data_len {
+        return Err(RuskError::Corruption { offset: None });
+    }
+
+    Ok(frame)
+}
+
+/// Decodes a frame read by [`read_frame`], optionally verifying its checksum.
+pub(crate) fn decode_frame(frame: &[u8], version: u32, verify: bool) -> Result<Record> {
+    if version == PLAINTEXT_VERSION {
+        return decode_plaintext(frame);
+    }
+
+    let data = &frame[frame_overhead(version) as usize..];
+
+    if verify && version != LEGACY_VERSION {
+        let start = LENGTH_PREFIX_LEN as usize;
+        let expected = u32::from_be_bytes(frame[start..start + 4].try_into().unwrap());
+        if crc32fast::hash(data) != expected {
+            return Err(RuskError::ChecksumMismatch);
+        }
+    }
+
+    if version == BINARY_VERSION {
+        return decode_binary(data);
+    }
+
+    let cmd: LogCommand = serde_json::from_slice(data)?;
+    Ok(cmd.into())
+}
+
+/// Moves `reader` from the start of a binary (version `3`) `Set` frame of
+/// `length` bytes to the first byte of its value, returning the value's
+/// length.
+///
+/// Only the record's framing and key are read, not its value, so this works
+/// for values of any size. If `verify` is set, the checksum is first checked
+/// by streaming the data through it in chunks. Returns
+/// `RuskError::UnexpectedCommand` if the frame holds a `Remove`.
+pub(crate) fn seek_binary_value<R: Read + Seek>(
+    reader: &mut R,
+    length: u64,
+    verify: bool,
+) -> Result<u64> {
+    let overhead = frame_overhead(BINARY_VERSION);
+    let data_start = reader.stream_position()? + overhead;
+
+    let mut prefix = [0u8; (LENGTH_PREFIX_LEN + CHECKSUM_LEN) as usize];
+    reader.read_exact(&mut prefix)?;
+    let data_len = u32::from_be_bytes(prefix[..4].try_into().unwrap()) as u64;
+    if overhead + data_len != length {
+        return Err(RuskError::Corruption { offset: None });
+    }
+
+    if verify {
+        let expected = u32::from_be_bytes(prefix[4..].try_into().unwrap());
+        let mut hasher = crc32fast::Hasher::new();
+        let mut data = reader.by_ref().take(data_len);
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = data.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        if hasher.finalize() != expected {
+            return Err(RuskError::ChecksumMismatch);
+        }
+        reader.seek(SeekFrom::Start(data_start))?;
+    }
+
+    // Tag, seq and key length.
+    let mut head = [0u8; 13];
+    if data_len < head.len() as u64 {
+        return Err(RuskError::Corruption { offset: None });
+    }
+    reader.read_exact(&mut head)?;
+    match head[0] {
+        SET_TAG | SET_WITH_META_TAG | SET_CREATED_TAG | SET_WITH_META_CREATED_TAG => {}
+        REMOVE_TAG => return Err(RuskError::UnexpectedCommand),
+        _ => return Err(RuskError::Corruption { offset: None }),
+    }
+
+    let key_len = u32::from_be_bytes(head[9..].try_into().unwrap()) as u64;
+    let value_start = head.len() as u64 + key_len + 4;
+    if value_start > data_len {
+        return Err(RuskError::Corruption { offset: None });
+    }
+    reader.seek(SeekFrom::Current(key_len as i64))?;
+
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf)?;
+    let value_len = u32::from_be_bytes(len_buf) as u64;
+    if value_start + value_len > data_len {
+        return Err(RuskError::Corruption { offset: None });
+    }
+
+    Ok(value_len)
+}
+
+/// Returns `true` if `s` can be stored as a key or value in the plaintext
+/// encoding.
+pub(crate) fn is_plaintext_safe(s: &str) -> bool {
+    !s.contains(['\t', '\n'])
+}
+
+fn encode_plaintext(record: &Record) -> Result<Vec<u8>> {
+    let line = match &record.command {
+        Command::Set {
+            key,
+            value,
+            meta: None,
+            created_at: None,
+        } if is_plaintext_safe(key) && is_plaintext_safe(value) => {
+            format!("{}\t{}\n", key, value)
+        }
+        Command::Remove { key } if is_plaintext_safe(key) => format!("-{}\n", key),
+        command => {
+            return Err(RuskError::PlaintextUnsupported {
+                key: command.key().to_owned(),
+            });
+        }
+    };
+    Ok(line.into_bytes())
+}
+
+/// Reads a plaintext record: everything up to and including the next
+/// newline, which must come within `limit` bytes.
+fn read_line(reader: &mut impl Read, limit: u64) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    // End of input before the first byte is an ordinary EOF, as for framed
+    // records; after it, the line was cut short.
+    reader.read_exact(&mut byte)?;
+    loop {
+        line.push(byte[0]);
+        if byte[0] == b'\n' {
+            return Ok(line);
+        }
+        if line.len() as u64 >= limit {
+            return Err(RuskError::Corruption { offset: None });
+        }
+        match reader.read_exact(&mut byte) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(RuskError::Corruption { offset: None });
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+fn decode_plaintext(line: &[u8]) -> Result<Record> {
+    let line = line
+        .strip_suffix(b"\n")
+        .ok_or(RuskError::Corruption { offset: None })?;
+    let line = std::str::from_utf8(line).map_err(|_| RuskError::Corruption { offset: None })?;
+
+    let command = if let Some((key, value)) = line.split_once('\t') {
+        Command::Set {
+            key: key.to_owned(),
+            value: value.to_owned(),
+            meta: None,
+            created_at: None,
+        }
+    } else if let Some(key) = line.strip_prefix('-') {
+        Command::Remove {
+            key: key.to_owned(),
+        }
+    } else {
+        return Err(RuskError::Corruption { offset: None });
+    };
+    Ok(Record { seq: 0, command })
+}
+
+fn encode_binary(record: &Record) -> Vec<u8> {
+    let mut data = Vec::new();
+    match &record.command {
+        Command::Set {
+            key,
+            value,
+            meta,
+            created_at,
+        } => {
+            data.push(match (meta, created_at) {
+                (None, None) => SET_TAG,
+                (Some(_), None) => SET_WITH_META_TAG,
+                (None, Some(_)) => SET_CREATED_TAG,
+                (Some(_), Some(_)) => SET_WITH_META_CREATED_TAG,
+            });
+            data.extend_from_slice(&record.seq.to_be_bytes());
+            put_string(&mut data, key);
+            put_string(&mut data, value);
+            if let Some(meta) = meta {
+                put_string(&mut data, meta);
+            }
+            if let Some(created_at) = created_at {
+                data.extend_from_slice(&created_at.to_be_bytes());
+            }
+        }
+        Command::Remove { key } => {
+            data.push(REMOVE_TAG);
+            data.extend_from_slice(&record.seq.to_be_bytes());
+            put_string(&mut data, key);
+        }
+    }
+    data
+}
+
+fn put_string(data: &mut Vec<u8>, s: &str) {
+    data.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    data.extend_from_slice(s.as_bytes());
+}
+
+/// Decodes binary record data, returning `RuskError::Corruption` if it is
+/// malformed.
+fn decode_binary(mut data: &[u8]) -> Result<Record> {
+    let tag = take_bytes(&mut data, 1)?[0];
+    let seq = u64::from_be_bytes(take_bytes(&mut data, 8)?.try_into().unwrap());
+    let key = take_string(&mut data)?;
+
+    let command = match tag {
+        SET_TAG | SET_WITH_META_TAG | SET_CREATED_TAG | SET_WITH_META_CREATED_TAG => Command::Set {
+            key,
+            value: take_string(&mut data)?,
+            meta: match tag {
+                SET_WITH_META_TAG | SET_WITH_META_CREATED_TAG => Some(take_string(&mut data)?),
+                _ => None,
+            },
+            created_at: match tag {
+                SET_CREATED_TAG | SET_WITH_META_CREATED_TAG => Some(u64::from_be_bytes(
+                    take_bytes(&mut data, 8)?.try_into().unwrap(),
+                )),
+                _ => None,
+            },
+        },
+        REMOVE_TAG => Command::Remove { key },
+        _ => return Err(RuskError::Corruption { offset: None }),
+    };
+    if !data.is_empty() {
+        return Err(RuskError::Corruption { offset: None });
+    }
+
+    Ok(Record { seq, command })
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(RuskError::Corruption { offset: None });
+    }
+    let (head, rest) = data.split_at(len);
+    *data = rest;
+    Ok(head)
+}
+
+fn take_string(data: &mut &[u8]) -> Result<String> {
+    let len = u32::from_be_bytes(take_bytes(data, 4)?.try_into().unwrap()) as usize;
+    let bytes = take_bytes(data, len)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| RuskError::Corruption { offset: None })
+}
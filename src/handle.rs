@@ -0,0 +1,277 @@
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::warn;
+
+use crate::engine::{CompactionReport, RuskStore};
+use crate::error::{Result, RuskError};
+
+/// A cheaply cloneable handle to a [`RuskStore`] owned by a background
+/// thread.
+///
+/// Every clone sends its requests over a channel to that thread, which
+/// applies them one at a time, so the store can be shared across an
+/// application without callers holding a lock. Requests are applied in the
+/// order they were queued. When the last clone is dropped the thread drops
+/// the store, flushing it, and the drop waits for that to finish.
+///
+/// The queue of requests waiting for the thread is bounded: once it is full,
+/// callers block until the thread catches up, so a burst of writes can't
+/// queue up unbounded memory.
+///
+/// If the store was opened with
+/// [`open_handle`](crate::RuskStoreBuilder::open_handle) and an
+/// [`auto_compact_interval`](crate::RuskStoreBuilder::auto_compact_interval),
+/// the thread also compacts it on that schedule when it has built up enough
+/// dead space.
+#[derive(Debug, Clone)]
+pub struct StoreHandle {
+    actor: Arc<Actor>,
+}
+
+enum Request {
+    Set {
+        key: String,
+        value: String,
+        reply: Sender<Result<()>>,
+    },
+    Get {
+        key: String,
+        reply: Sender<Result<Option<String>>>,
+    },
+    Remove {
+        key: String,
+        reply: Sender<Result<()>>,
+    },
+    Compact {
+        reply: Sender<Result<CompactionReport>>,
+    },
+    /// Sent by the [`Ticker`]: compact if there is enough dead space.
+    Tick,
+}
+
+#[derive(Debug)]
+struct Actor {
+    ticker: Option<Ticker>,
+    requests: Option<SyncSender<Request>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Default for [`StoreHandle::with_capacity`].
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+impl StoreHandle {
+    /// Moves `store` onto a new background thread and returns a handle to
+    /// it, queueing up to 1024 requests.
+    pub fn new(store: RuskStore) -> Self {
+        Self::with_capacity(store, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Like [`new`](Self::new), but queues at most `capacity` requests
+    /// before callers block. With a capacity of `0` every caller waits until
+    /// the thread picks its request up.
+    pub fn with_capacity(mut store: RuskStore, capacity: usize) -> Self {
+        let (requests, rx) = mpsc::sync_channel::<Request>(capacity);
+
+        let interval = store.auto_compact_interval();
+
+        let thread = thread::spawn(move || {
+            // A caller that gave up waiting for its reply is not an error.
+            for request in rx {
+                match request {
+                    Request::Set { key, value, reply } => {
+                        let _ = reply.send(store.set(key, value));
+                    }
+                    Request::Get { key, reply } => {
+                        let _ = reply.send(store.get(key));
+                    }
+                    Request::Remove { key, reply } => {
+                        let _ = reply.send(store.remove(key));
+                    }
+                    Request::Compact { reply } => {
+                        let _ = reply.send(store.compact());
+                    }
+                    Request::Tick => {
+                        if let Err(err) = store.maybe_compact() {
+                            warn!("scheduled compaction failed: {}", err);
+                        }
+                    }
+                }
+            }
+        });
+
+        StoreHandle {
+            actor: Arc::new(Actor {
+                ticker: interval.map(|interval| Ticker::spawn(&requests, interval)),
+                requests: Some(requests),
+                thread: Some(thread),
+            }),
+        }
+    }
+
+    /// Sets a key-value pair. See [`RuskStore::set`].
+    pub fn set(&self, key: String, value: String) -> Result<()> {
+        self.call(|reply| Request::Set { key, value, reply })
+    }
+
+    /// Gets the value for a key. See [`RuskStore::get`].
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        self.call(|reply| Request::Get { key, reply })
+    }
+
+    /// Removes a key. See [`RuskStore::remove`].
+    pub fn remove(&self, key: String) -> Result<()> {
+        self.call(|reply| Request::Remove { key, reply })
+    }
+
+    /// Compacts the log. See [`RuskStore::compact`].
+    pub fn compact(&self) -> Result<CompactionReport> {
+        self.call(|reply| Request::Compact { reply })
+    }
+
+    /// Sends a request to the store thread and waits for its reply.
+    fn call<T>(&self, request: impl FnOnce(Sender<Result<T>>) -> Request) -> Result<T> {
+        let (reply, response) = mpsc::channel();
+        if let Some(requests) = &self.actor.requests {
+            // If the thread has stopped, the request (and with it the reply
+            // sender) is dropped and `recv` below fails.
+            let _ = requests.send(request(reply));
+        }
+        response.recv().unwrap_or_else(|_| {
+            Err(RuskError::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "store thread has stopped",
+            )))
+        })
+    }
+}
+
+impl Drop for Actor {
+    fn drop(&mut self) {
+        // The ticker holds a sender too, so stop it before closing the
+        // channel, which ends the thread's loop and drops the store.
+        if let Some(ticker) = self.ticker.take() {
+            ticker.stop();
+        }
+        drop(self.requests.take());
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("store thread panicked");
+        }
+    }
+}
+
+/// Queues a [`Request::Tick`] every `interval` until stopped, driving a
+/// handle's [`auto_compact_interval`](crate::RuskStoreBuilder::auto_compact_interval).
+#[derive(Debug)]
+struct Ticker {
+    shutdown: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Ticker {
+    fn spawn(requests: &SyncSender<Request>, interval: Duration) -> Self {
+        let requests = requests.clone();
+        let (shutdown, shutdown_rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = shutdown_rx.recv_timeout(interval) {
+                // A full queue means the store is busy; the next tick will
+                // check again.
+                if let Err(TrySendError::Disconnected(_)) = requests.try_send(Request::Tick) {
+                    break;
+                }
+            }
+        });
+
+        Ticker { shutdown, handle }
+    }
+
+    fn stop(self) {
+        drop(self.shutdown);
+        let _ = self.handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::engine::LOG_FILE_NAME;
+
+    /// Queues a tick as the ticker would, then waits for the store thread
+    /// to get past it.
+    fn tick(handle: &StoreHandle) {
+        let requests = handle.actor.requests.as_ref().unwrap();
+        requests.send(Request::Tick).unwrap();
+        handle.get(String::new()).unwrap();
+    }
+
+    #[test]
+    fn tick_compacts_store_with_dead_space() {
+        let dir = TempDir::new().unwrap();
+        let mut store = RuskStore::open(dir.path()).unwrap();
+        for i in 0..1000 {
+            store.set("key".to_owned(), i.to_string()).unwrap();
+        }
+        drop(store);
+        let log_path = dir.path().join(LOG_FILE_NAME);
+        let size = fs::metadata(&log_path).unwrap().len();
+
+        // Long enough that only the injected tick can trigger compaction.
+        let handle = RuskStore::builder(dir.path())
+            .compaction_threshold(1024)
+            .auto_compact_interval(Duration::from_secs(3600))
+            .open_handle()
+            .unwrap();
+        assert_eq!(fs::metadata(&log_path).unwrap().len(), size);
+        tick(&handle);
+
+        assert!(fs::metadata(&log_path).unwrap().len() < size);
+        assert_eq!(
+            handle.get("key".to_owned()).unwrap().as_deref(),
+            Some("999")
+        );
+    }
+
+    #[test]
+    fn tick_leaves_clean_store_alone() {
+        let dir = TempDir::new().unwrap();
+        let handle = RuskStore::builder(dir.path())
+            .compaction_threshold(0)
+            .auto_compact_interval(Duration::from_secs(3600))
+            .open_handle()
+            .unwrap();
+        handle.set("key".to_owned(), "value".to_owned()).unwrap();
+        tick(&handle);
+        tick(&handle);
+        drop(handle);
+
+        let store = RuskStore::open(dir.path()).unwrap();
+        assert_eq!(store.generation(), 0);
+    }
+
+    #[test]
+    fn auto_compact_interval_requires_a_handle() {
+        let dir = TempDir::new().unwrap();
+        let builder =
+            || RuskStore::builder(dir.path()).auto_compact_interval(Duration::from_secs(1));
+
+        assert!(matches!(
+            builder().open(),
+            Err(RuskError::AutoCompactRequiresHandle)
+        ));
+        assert!(matches!(
+            builder().open_with_hasher(std::hash::RandomState::new()),
+            Err(RuskError::AutoCompactRequiresHandle)
+        ));
+        builder().open_handle().unwrap();
+    }
+}
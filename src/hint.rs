@@ -0,0 +1,86 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{retry_io, write_all_retrying};
+use crate::error::Result;
+
+/// A snapshot of the in-memory index, persisted next to the log so a later
+/// open can skip replaying it.
+///
+/// A hint is only trusted if its `generation` and `log_len` match the log it
+/// describes; anything else means the log has changed since it was written.
+///
+/// On disk the hint is its JSON serialization preceded by a CRC32 of it
+/// (4 bytes, big-endian), so a hint damaged after it was written is
+/// discarded rather than loaded with wrong offsets.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Hint {
+    pub(crate) generation: u64,
+    pub(crate) log_len: u64,
+    pub(crate) seq: u64,
+    pub(crate) uncompacted: u64,
+    /// Number of tombstones in the hinted part of the log. Hints written
+    /// before this was recorded lack it and are ignored.
+    #[serde(default)]
+    pub(crate) tombstones: Option<u64>,
+    pub(crate) entries: Vec<HintEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HintEntry {
+    pub(crate) key: String,
+    pub(crate) offset: u64,
+    pub(crate) length: u64,
+    pub(crate) seq: u64,
+}
+
+impl Hint {
+    /// Loads the hint at `path`, returning `None` if it is missing,
+    /// unreadable or fails its checksum.
+    pub(crate) fn load(path: &Path) -> Option<Hint> {
+        let bytes = fs::read(path).ok()?;
+        let Some((checksum, json)) = bytes.split_first_chunk::<4>() else {
+            debug!("ignoring truncated hint {}", path.display());
+            return None;
+        };
+        if crc32fast::hash(json) != u32::from_be_bytes(*checksum) {
+            debug!("ignoring hint {} with bad checksum", path.display());
+            return None;
+        }
+        match serde_json::from_slice(json) {
+            Ok(hint) => Some(hint),
+            Err(err) => {
+                debug!("ignoring unreadable hint {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    /// Writes the hint to `path`, replacing any previous hint atomically.
+    ///
+    /// Transient IO errors are retried up to `retries` times. On failure the
+    /// partly written temporary file is removed, so a full disk isn't left
+    /// fuller.
+    pub(crate) fn save(&self, path: &Path, retries: usize) -> Result<()> {
+        let tmp_path = path.with_extension("hint.tmp");
+
+        let json = serde_json::to_vec(self)?;
+        let result = retry_io(retries, || File::create(&tmp_path)).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write_all_retrying(&mut writer, &crc32fast::hash(&json).to_be_bytes(), retries)?;
+            write_all_retrying(&mut writer, &json, retries)?;
+            retry_io(retries, || writer.flush())?;
+            drop(writer);
+
+            retry_io(retries, || fs::rename(&tmp_path, path))
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        Ok(result?)
+    }
+}
@@ -0,0 +1,64 @@
+use std::hash::BuildHasher;
+
+use crate::engine::RuskStore;
+use crate::error::Result;
+use crate::handle::StoreHandle;
+
+/// The basic operations of a key-value store, for code that should be
+/// generic over the storage backend.
+///
+/// Methods take `&mut self` and return [`Result`] because that is what
+/// [`RuskStore`] needs: reads may go to disk and can fail, and may update
+/// internal state such as the cached reader.
+pub trait KeyValueStore {
+    /// Sets the value for `key`, replacing any previous value.
+    fn set(&mut self, key: String, value: String) -> Result<()>;
+
+    /// Gets the value for `key`, or `None` if it doesn't exist.
+    fn get(&mut self, key: String) -> Result<Option<String>>;
+
+    /// Removes `key`.
+    ///
+    /// Returns `RuskError::KeyNotFound` if the key doesn't exist.
+    fn remove(&mut self, key: String) -> Result<()>;
+
+    /// Reclaims space taken by overwritten and removed values, where the
+    /// backend has any to reclaim.
+    fn compact(&mut self) -> Result<()>;
+}
+
+impl<S: BuildHasher + Clone> KeyValueStore for RuskStore<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        RuskStore::set(self, key, value)
+    }
+
+    fn get(&mut self, key: String) -> Result<Option<String>> {
+        RuskStore::get(self, key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<()> {
+        RuskStore::remove(self, key)
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        RuskStore::compact(self).map(|_| ())
+    }
+}
+
+impl KeyValueStore for StoreHandle {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        StoreHandle::set(self, key, value)
+    }
+
+    fn get(&mut self, key: String) -> Result<Option<String>> {
+        StoreHandle::get(self, key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<()> {
+        StoreHandle::remove(self, key)
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        StoreHandle::compact(self).map(|_| ())
+    }
+}
@@ -1,5 +1,34 @@
+mod builder;
+mod cache;
+mod config;
 mod engine;
 mod error;
+pub mod format;
+mod handle;
+mod hint;
+mod kv;
+mod memory;
+mod reader;
+mod removed;
+mod repair;
+#[cfg(feature = "testutil")]
+pub mod testutil;
 
-pub use engine::RuskStore;
+pub use builder::{
+    DurabilityMode, Encoding, FlushMode, ImportErrorPolicy, InvalidCounterPolicy, MirrorPolicy,
+    RuskStoreBuilder, WriteBatchPolicy,
+};
+pub use cache::CacheStats;
+pub use config::RuskConfig;
+pub use engine::{
+    AutoCompaction, CompactionEstimate, CompactionReport, ConflictPolicy, Entry, HistoryEntry,
+    ImportReport, KeyStatus, LastWrite, MergeReport, Metrics, ProbeReport, ReplayProgress,
+    RuskStore, SizeHistogram, WriteKind, with_store,
+};
 pub use error::{Result, RuskError};
+pub use format::Command;
+pub use handle::StoreHandle;
+pub use kv::KeyValueStore;
+pub use memory::MemoryStore;
+pub use reader::RuskReader;
+pub use repair::{RepairPolicy, RepairReport};
@@ -0,0 +1,204 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::error::{Result, RuskError};
+use crate::kv::KeyValueStore;
+
+/// A store that keeps its data in memory only, with the same basic API as
+/// [`RuskStore`](crate::RuskStore).
+///
+/// Nothing is ever written to disk and the data is gone when the store is
+/// dropped, which suits tests and throwaway caches. Create one with
+/// [`RuskStore::in_memory`](crate::RuskStore::in_memory) or
+/// [`MemoryStore::bounded`].
+///
+/// This is a separate type rather than a mode of `RuskStore` because much of
+/// `RuskStore`'s API (offsets, generations, [`tail`](crate::RuskStore::tail),
+/// hint files) describes the log and has no meaning without one. Code that
+/// should work with either backend can be written against
+/// [`KeyValueStore`].
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    capacity: Option<usize>,
+    map: HashMap<String, (String, u64)>,
+    // Like the value cache, a bounded store stamps each use so that stale
+    // queue entries (for keys used again since) never evict a more recent
+    // one. Unbounded stores leave the queue empty.
+    order: VecDeque<(String, u64)>,
+    next_stamp: u64,
+}
+
+impl MemoryStore {
+    /// Creates an empty store with no limit on the number of keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty store holding at most `capacity` keys.
+    ///
+    /// Once a `set` would exceed the limit, the least recently used key
+    /// (by `get` or `set`) is evicted to make room, as in a cache. With a
+    /// capacity of `0` nothing is kept.
+    pub fn bounded(capacity: usize) -> Self {
+        MemoryStore {
+            capacity: Some(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Sets a key-value pair.
+    ///
+    /// If the key already exists, the value is overwritten. In a
+    /// [bounded](Self::bounded) store this may evict the least recently used
+    /// key.
+    pub fn set(&mut self, key: String, value: String) -> Result<()> {
+        if self.capacity == Some(0) {
+            return Ok(());
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        if let Some(capacity) = self.capacity {
+            self.order.push_back((key.clone(), stamp));
+            self.map.insert(key, (value, stamp));
+            while self.map.len() > capacity {
+                self.evict_oldest();
+            }
+            self.drop_stale();
+        } else {
+            self.map.insert(key, (value, stamp));
+        }
+        Ok(())
+    }
+
+    /// Gets the value for a key.
+    ///
+    /// Returns `None` if the key doesn't exist, or was evicted from a
+    /// [bounded](Self::bounded) store.
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        let stamp = self.next_stamp;
+        let Some((value, entry_stamp)) = self.map.get_mut(&key) else {
+            return Ok(None);
+        };
+        *entry_stamp = stamp;
+        let value = value.clone();
+
+        self.next_stamp += 1;
+        if self.capacity.is_some() {
+            self.order.push_back((key, stamp));
+            self.drop_stale();
+        }
+        Ok(Some(value))
+    }
+
+    /// Removes a key from the store.
+    ///
+    /// Returns `RuskError::KeyNotFound` if the key doesn't exist.
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        match self.map.remove(&key) {
+            Some(_) => Ok(()),
+            None => Err(RuskError::KeyNotFound(key)),
+        }
+    }
+
+    /// Does nothing: there is no log to compact. Present so the store can
+    /// stand in for a [`RuskStore`](crate::RuskStore).
+    pub fn compact(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the number of keys.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the store contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the most keys the store holds, or `None` if it is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    fn evict_oldest(&mut self) {
+        while let Some((key, stamp)) = self.order.pop_front() {
+            if self.map.get(&key).map(|(_, current)| *current) == Some(stamp) {
+                self.map.remove(&key);
+                return;
+            }
+        }
+    }
+
+    /// Stale entries accumulate with every use; drop them before the queue
+    /// grows much past the stored set.
+    fn drop_stale(&mut self) {
+        if self.order.len() > self.map.len().max(1) * 2 {
+            let map = &self.map;
+            self.order
+                .retain(|(key, stamp)| map.get(key).map(|(_, current)| *current) == Some(*stamp));
+        }
+    }
+}
+
+impl KeyValueStore for MemoryStore {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        MemoryStore::set(self, key, value)
+    }
+
+    fn get(&mut self, key: String) -> Result<Option<String>> {
+        MemoryStore::get(self, key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<()> {
+        MemoryStore::remove(self, key)
+    }
+
+    fn compact(&mut self) -> Result<()> {
+        MemoryStore::compact(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_store_evicts_least_recently_used_key() {
+        let mut store = MemoryStore::bounded(2);
+        store.set("a".to_owned(), "1".to_owned()).unwrap();
+        store.set("b".to_owned(), "2".to_owned()).unwrap();
+        // Using "a" leaves "b" as the least recently used.
+        assert_eq!(store.get("a".to_owned()).unwrap().as_deref(), Some("1"));
+        store.set("c".to_owned(), "3".to_owned()).unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("b".to_owned()).unwrap(), None);
+        assert_eq!(store.get("a".to_owned()).unwrap().as_deref(), Some("1"));
+        assert_eq!(store.get("c".to_owned()).unwrap().as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn bounded_store_stays_bounded_under_churn() {
+        let mut store = MemoryStore::bounded(10);
+        for i in 0..10_000 {
+            store.set(format!("key{}", i % 100), i.to_string()).unwrap();
+            store.get(format!("key{}", i % 7)).unwrap();
+        }
+
+        assert_eq!(store.len(), 10);
+        assert!(store.order.len() <= 2 * 10 + 1);
+    }
+
+    #[test]
+    fn unbounded_store_keeps_everything() {
+        let mut store = crate::RuskStore::in_memory();
+        for i in 0..1000 {
+            store.set(format!("key{}", i), i.to_string()).unwrap();
+        }
+
+        assert_eq!(store.capacity(), None);
+        assert_eq!(store.len(), 1000);
+        assert!(store.order.is_empty());
+    }
+}
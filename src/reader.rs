@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::engine::{CommandPos, read_record_at};
+use crate::error::{Result, RuskError};
+use crate::format::Command;
+
+/// A read-only snapshot of a [`RuskStore`](crate::RuskStore).
+///
+/// A reader sees the store as it was when [`RuskStore::reader`] was called:
+/// later writes are not visible. It holds its own handle to the log, so it
+/// keeps working after the store compacts (the old log stays readable until
+/// every clone of the reader is dropped). Cloning is cheap and clones share
+/// the same snapshot.
+///
+/// [`RuskStore::reader`]: crate::RuskStore::reader
+#[derive(Clone)]
+pub struct RuskReader {
+    file: Arc<Mutex<Box<dyn ReadSeek>>>,
+    index: Arc<HashMap<String, CommandPos>>,
+    version: u32,
+    verify: bool,
+    key_transform: Option<fn(&str) -> String>,
+}
+
+/// The log a [`RuskReader`] reads values from.
+pub(crate) trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+impl fmt::Debug for RuskReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuskReader")
+            .field("len", &self.index.len())
+            .field("version", &self.version)
+            .field("verify", &self.verify)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RuskReader {
+    pub(crate) fn new(
+        file: Box<dyn ReadSeek>,
+        index: HashMap<String, CommandPos>,
+        version: u32,
+        verify: bool,
+        key_transform: Option<fn(&str) -> String>,
+    ) -> Self {
+        RuskReader {
+            file: Arc::new(Mutex::new(file)),
+            index: Arc::new(index),
+            version,
+            verify,
+            key_transform,
+        }
+    }
+
+    /// Gets the value for a key as of the snapshot.
+    ///
+    /// Returns `None` if the key didn't exist when the snapshot was taken.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let key = match self.key_transform {
+            Some(transform) => transform(key),
+            None => key.to_owned(),
+        };
+
+        let Some(cmd_pos) = self.index.get(&key) else {
+            return Ok(None);
+        };
+
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        match read_record_at(&mut *file, cmd_pos, self.version, self.verify)?.command {
+            Command::Set { value, .. } => Ok(Some(value)),
+            Command::Remove { .. } => Err(RuskError::IndexMismatch {
+                key,
+                offset: cmd_pos.offset,
+            }),
+        }
+    }
+
+    /// Returns an iterator over the keys in the snapshot, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    /// Returns the number of keys in the snapshot.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns `true` if the snapshot contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::{HashMap, VecDeque};
+
+/// A bounded record of the most recently removed keys.
+///
+/// Keys are evicted oldest-first once `capacity` is exceeded. Each removal is
+/// stamped so that stale queue entries (for keys that were set again or
+/// removed more than once) never evict a newer removal.
+#[derive(Debug)]
+pub(crate) struct RecentlyRemoved {
+    capacity: usize,
+    stamps: HashMap<String, u64>,
+    order: VecDeque<(String, u64)>,
+    next_stamp: u64,
+}
+
+impl RecentlyRemoved {
+    pub(crate) fn new(capacity: usize) -> Self {
+        RecentlyRemoved {
+            capacity,
+            stamps: HashMap::new(),
+            order: VecDeque::new(),
+            next_stamp: 0,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.stamps.insert(key.clone(), stamp);
+        self.order.push_back((key, stamp));
+
+        while self.stamps.len() > self.capacity {
+            self.evict_oldest();
+        }
+
+        // Stale entries only accumulate under heavy churn; drop them before
+        // the queue grows much past the live set.
+        if self.order.len() > self.capacity * 2 {
+            let stamps = &self.stamps;
+            self.order
+                .retain(|(key, stamp)| stamps.get(key) == Some(stamp));
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.stamps.remove(key);
+    }
+
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        self.stamps.contains_key(key)
+    }
+
+    fn evict_oldest(&mut self) {
+        while let Some((key, stamp)) = self.order.pop_front() {
+            if self.stamps.get(&key) == Some(&stamp) {
+                self.stamps.remove(&key);
+                return;
+            }
+        }
+    }
+}
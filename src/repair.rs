@@ -0,0 +1,98 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::error::{Result, RuskError};
+use crate::format::{self, Header};
+
+/// What [`RuskStore::repair`](crate::RuskStore::repair) does when it reaches
+/// a record it can't read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepairPolicy {
+    /// Stop at the first unreadable record, keeping everything before it.
+    #[default]
+    Stop,
+    /// Skip ahead to the next readable record and keep going.
+    Skip,
+}
+
+/// What a repair salvaged. See [`RuskStore::repair`](crate::RuskStore::repair).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of records copied into the repaired log.
+    pub records_recovered: u64,
+    /// Number of bytes of the original log that couldn't be read.
+    pub bytes_skipped: u64,
+}
+
+/// Copies every readable record of the log at `src` into a new log at `dest`.
+///
+/// The source log is read into memory in full and never modified.
+pub(crate) fn repair_log(src: &Path, dest: &Path, policy: RepairPolicy) -> Result<RepairReport> {
+    if fs::metadata(dest).is_ok_and(|metadata| metadata.len() > format::MAX_HEADER_LEN) {
+        return Err(RuskError::AlreadyExists);
+    }
+
+    let bytes = fs::read(src)?;
+    let header = format::decode_header(&bytes).unwrap_or(Header {
+        version: format::LEGACY_VERSION,
+        generation: 0,
+    });
+    format::check_version(header.version)?;
+    let version = header.version;
+
+    // Legacy logs are upgraded so the repaired records are checksummed.
+    let dest_version = match version {
+        format::LEGACY_VERSION => format::CURRENT_VERSION,
+        version => version,
+    };
+
+    let mut writer = BufWriter::new(File::create(dest)?);
+    writer.write_all(&format::encode_header(&Header {
+        version: dest_version,
+        generation: 0,
+    }))?;
+
+    let mut report = RepairReport::default();
+    let mut pos = format::data_start(version) as usize;
+    while pos < bytes.len() {
+        if let Ok((record, length)) = format::decode_record(&bytes[pos..], version) {
+            writer.write_all(&format::encode_record(&record, dest_version)?)?;
+            report.records_recovered += 1;
+            pos += length;
+            continue;
+        }
+
+        let next = match policy {
+            RepairPolicy::Stop => bytes.len(),
+            // Without a trustworthy length there is no way to know where the
+            // next record starts, so try every offset until one decodes.
+            RepairPolicy::Skip => (pos + 1..bytes.len())
+                .find(|&offset| format::decode_record(&bytes[offset..], version).is_ok())
+                .unwrap_or(bytes.len()),
+        };
+        warn!(
+            "skipping {} unreadable bytes at offset {} in {}",
+            next - pos,
+            pos,
+            src.display()
+        );
+        report.bytes_skipped += (next - pos) as u64;
+        pos = next;
+    }
+
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    info!(
+        "repaired {} into {}: {} records recovered, {} bytes skipped",
+        src.display(),
+        dest.display(),
+        report.records_recovered,
+        report.bytes_skipped
+    );
+
+    Ok(report)
+}
@@ -0,0 +1,65 @@
+//! Helpers for testing code built on rusk against a real store.
+//!
+//! Only available with the `testutil` feature, which is off by default;
+//! enable it for `dev-dependencies` only.
+
+use std::collections::HashMap;
+
+use tempfile::TempDir;
+
+use crate::engine::RuskStore;
+
+/// Opens a new, empty store in a fresh temporary directory.
+///
+/// The directory and everything in it are deleted when the returned
+/// [`TempDir`] is dropped, so keep it alive for as long as the store.
+///
+/// # Panics
+///
+/// Panics if the directory can't be created or the store can't be opened.
+pub fn temp_store() -> (RuskStore, TempDir) {
+    let dir = tempfile::tempdir().expect("failed to create temporary directory");
+    let store = RuskStore::open(dir.path()).expect("failed to open store");
+    (store, dir)
+}
+
+/// Asserts that the live entries of `store` are exactly `expected`.
+///
+/// # Panics
+///
+/// Panics, listing the differing keys, if `store` has a key `expected`
+/// doesn't, lacks one it has, or holds a different value; or if reading the
+/// store fails.
+pub fn assert_store_eq(store: &mut RuskStore, expected: &HashMap<String, String>) {
+    let mut keys: Vec<String> = store.keys().map(str::to_owned).collect();
+    keys.sort_unstable();
+
+    let mut differences = Vec::new();
+    for key in &keys {
+        let actual = store.get(key.clone()).expect("failed to read store");
+        match (actual, expected.get(key)) {
+            (Some(actual), Some(expected)) if actual == *expected => {}
+            (Some(actual), Some(expected)) => differences.push(format!(
+                "{}: expected {:?}, found {:?}",
+                key, expected, actual
+            )),
+            (Some(actual), None) => {
+                differences.push(format!("{}: unexpected, found {:?}", key, actual))
+            }
+            (None, _) => unreachable!("listed keys are live"),
+        }
+    }
+
+    let mut missing: Vec<&String> = expected
+        .keys()
+        .filter(|key| keys.binary_search(key).is_err())
+        .collect();
+    missing.sort_unstable();
+    differences.extend(missing.into_iter().map(|key| format!("{}: missing", key)));
+
+    assert!(
+        differences.is_empty(),
+        "store doesn't match expected entries:\n  {}",
+        differences.join("\n  ")
+    );
+}